@@ -0,0 +1,390 @@
+use axum::{Json, extract::State, http::StatusCode};
+use chrono::{Duration, Utc};
+use garde::Validate;
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    mailer::Mail,
+    result::Result,
+};
+use lib_entity::{
+    email_verification, password_reset,
+    prelude::{EmailVerification, PasswordReset, Users},
+    sea_orm_active_enums::AuthType,
+    users,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set,
+    TransactionTrait, prelude::Uuid, sea_query::Expr,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::token;
+
+/// Lifetime of an email verification link in seconds.
+const VERIFICATION_EXPIRY: i64 = 60 * 60 * 24;
+/// Lifetime of a password reset link in seconds.
+const RESET_EXPIRY: i64 = 60 * 60;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(register))
+        .routes(routes!(verify_email))
+        .routes(routes!(resend_verification))
+        .routes(routes!(forgot_password))
+        .routes(routes!(reset_password))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+struct RegisterDTO {
+    #[garde(email)]
+    #[schema(example = "johndoe@email.com")]
+    email: String,
+    #[garde(custom(lib_security::password::policy))]
+    #[schema(example = "RandomPassword1!")]
+    password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TokenDTO {
+    #[schema(example = "<TOKEN>")]
+    token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct EmailDTO {
+    #[schema(example = "johndoe@email.com")]
+    email: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+struct ResetPasswordDTO {
+    #[garde(skip)]
+    #[schema(example = "<TOKEN>")]
+    token: String,
+    #[garde(custom(lib_security::password::policy))]
+    #[schema(example = "RandomPassword1!")]
+    password: String,
+}
+
+async fn send_verification<C>(state: &AppState, db: &C, user: &users::Model) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    let secret = token::generate_secret();
+    let now = Utc::now().naive_utc();
+
+    email_verification::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        token_hash: Set(token::hash_secret(&secret)),
+        expires_at: Set(now + Duration::seconds(VERIFICATION_EXPIRY)),
+        used_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    state
+        .mailer
+        .send(Mail {
+            to: user.email.clone(),
+            subject: "Verify your email address".into(),
+            body: format!(
+                "Open the link below to verify your email address:\n\n{}/auth/verify-email?token={}",
+                state.config.server.public_url, secret
+            ),
+        })
+        .await
+}
+
+/// Runs `work` after the response is sent, so whether an account exists, and mail is sent
+/// to it, never shows in the response time.
+fn in_background<F>(what: &'static str, work: F)
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(err) = work.await {
+            tracing::error!(?err, "failed to send the {what}");
+        }
+    });
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/register",
+    request_body(content = RegisterDTO, content_type = "application/json"),
+    responses(
+        (
+            status = 202,
+            description = "A verification email is sent, or a notice when the address is already registered"
+        ),
+        (status = 422, description = "Invalid email or password", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn register(
+    State(state): State<AppState>,
+    Json(payload): Json<RegisterDTO>,
+) -> Result<StatusCode> {
+    payload.validate().map_err(Error::Garde)?;
+
+    let existing = Users::find()
+        .filter(users::Column::Email.eq(&payload.email))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    // the response never reveals whether the account exists, the owner is told instead
+    if let Some(existing) = existing {
+        // as long as hashing the password of a new account
+        lib_security::password::verify_dummy(&state.password_params, &payload.password).await?;
+
+        state
+            .mailer
+            .send(Mail {
+                to: existing.email,
+                subject: "Your email address is already registered".into(),
+                body: format!(
+                    "Someone tried to create an account with this email address, which already has one.\n\nIf it was you, sign in or choose a new password at:\n\n{}/auth/forgot-password\n\nOtherwise you can ignore this email.",
+                    state.config.server.public_url
+                ),
+            })
+            .await?;
+
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    // hashed before the transaction holds a connection
+    let password = lib_security::password::hash(&state.password_params, &payload.password).await?;
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let user = users::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        auth_type: Set(AuthType::BasicAuth),
+        email: Set(payload.email),
+        password: Set(password),
+        email_verified_at: Set(None),
+        ..Default::default()
+    }
+    .insert(&trx)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    send_verification(&state, &trx, &user).await?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/verify-email",
+    request_body(content = TokenDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Email address verified"),
+        (status = 401, description = "Invalid or expired token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<TokenDTO>,
+) -> Result<StatusCode> {
+    let now = Utc::now().naive_utc();
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let verification = EmailVerification::find()
+        .filter(email_verification::Column::TokenHash.eq(token::hash_secret(&payload.token)))
+        .filter(email_verification::Column::UsedAt.is_null())
+        .filter(email_verification::Column::ExpiresAt.gt(now))
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    let consumed = EmailVerification::update_many()
+        .col_expr(email_verification::Column::UsedAt, Expr::value(now))
+        .filter(email_verification::Column::Id.eq(verification.id))
+        .filter(email_verification::Column::UsedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if consumed.rows_affected != 1 {
+        return Err(Error::AuthenticationError);
+    }
+
+    Users::update_many()
+        .col_expr(users::Column::EmailVerifiedAt, Expr::value(now))
+        .col_expr(users::Column::UpdatedAt, Expr::value(now))
+        .filter(users::Column::Id.eq(verification.user_id))
+        .filter(users::Column::EmailVerifiedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/verify-email/resend",
+    request_body(content = EmailDTO, content_type = "application/json"),
+    responses(
+        (status = 202, description = "A verification email is sent if the account exists and is unverified")
+    )
+)]
+async fn resend_verification(
+    State(state): State<AppState>,
+    Json(payload): Json<EmailDTO>,
+) -> StatusCode {
+    in_background("verification email", async move {
+        let user = Users::find()
+            .filter(users::Column::Email.eq(&payload.email))
+            .filter(users::Column::EmailVerifiedAt.is_null())
+            .one(&state.db)
+            .await
+            .map_err(Error::SeaOrm)?;
+
+        match user {
+            Some(user) => send_verification(&state, &state.db, &user).await,
+            None => Ok(()),
+        }
+    });
+
+    StatusCode::ACCEPTED
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/forgot-password",
+    request_body(content = EmailDTO, content_type = "application/json"),
+    responses(
+        (status = 202, description = "A reset link is sent if the account exists")
+    )
+)]
+async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<EmailDTO>,
+) -> StatusCode {
+    in_background("password reset email", async move {
+        let user = Users::find()
+            .filter(users::Column::Email.eq(&payload.email))
+            .one(&state.db)
+            .await
+            .map_err(Error::SeaOrm)?;
+
+        let Some(user) = user else {
+            return Ok(());
+        };
+
+        let secret = token::generate_secret();
+        let now = Utc::now().naive_utc();
+
+        password_reset::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            user_id: Set(user.id),
+            token_hash: Set(token::hash_secret(&secret)),
+            expires_at: Set(now + Duration::seconds(RESET_EXPIRY)),
+            used_at: Set(None),
+            created_at: Set(now),
+        }
+        .insert(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+        state
+            .mailer
+            .send(Mail {
+                to: user.email,
+                subject: "Reset your password".into(),
+                body: format!(
+                    "Open the link below to choose a new password:\n\n{}/auth/reset-password?token={}\n\nIf you did not request this you can ignore this email.",
+                    state.config.server.public_url, secret
+                ),
+            })
+            .await
+    });
+
+    StatusCode::ACCEPTED
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/reset-password",
+    request_body(content = ResetPasswordDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Password changed, existing sessions are signed out"),
+        (status = 401, description = "Invalid or expired token", body = ErrorResponse),
+        (status = 422, description = "Invalid password", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordDTO>,
+) -> Result<StatusCode> {
+    payload.validate().map_err(Error::Garde)?;
+
+    let now = Utc::now().naive_utc();
+    let password = lib_security::password::hash(&state.password_params, &payload.password).await?;
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let reset = PasswordReset::find()
+        .filter(password_reset::Column::TokenHash.eq(token::hash_secret(&payload.token)))
+        .filter(password_reset::Column::UsedAt.is_null())
+        .filter(password_reset::Column::ExpiresAt.gt(now))
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    let consumed = PasswordReset::update_many()
+        .col_expr(password_reset::Column::UsedAt, Expr::value(now))
+        .filter(password_reset::Column::Id.eq(reset.id))
+        .filter(password_reset::Column::UsedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if consumed.rows_affected != 1 {
+        return Err(Error::AuthenticationError);
+    }
+
+    let user = Users::find_by_id(reset.user_id)
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::RowNotFound)?;
+
+    let verified_at = user.email_verified_at.unwrap_or(now);
+
+    let mut user: users::ActiveModel = user.into();
+    user.password = Set(password);
+    // receiving the reset link proves ownership of the address
+    user.email_verified_at = Set(Some(verified_at));
+    user.updated_at = Set(now);
+    user.update(&trx).await.map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    lib_security::revocation::revoke_user_sessions(&state, reset.user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,241 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use garde::Validate;
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    result::Result,
+};
+use lib_entity::{api_key, prelude::ApiKey};
+use lib_security::{JWTClaim, api_key as keys, scope};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, prelude::Uuid,
+    sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::token;
+
+/// Longest lifetime a key may be given, one year in seconds.
+const MAX_EXPIRES_IN: u64 = 365 * 24 * 60 * 60;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(list_api_keys, create_api_key))
+        .routes(routes!(revoke_api_key))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+struct CreateApiKeyDTO {
+    #[garde(length(min = 1, max = 100))]
+    #[schema(example = "Warehouse scanner 3")]
+    name: String,
+    /// Scopes the key is limited to, only those held by the caller and carried by their
+    /// access token are kept.
+    #[garde(length(min = 1))]
+    #[schema(example = json!(["shipment:read", "shipment:update"]))]
+    scopes: Vec<String>,
+    /// Lifetime of the key in seconds, at most a year, the key does not expire when omitted.
+    #[garde(range(min = 1, max = MAX_EXPIRES_IN))]
+    #[schema(example = 7776000)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ApiKeyDTO {
+    id: Uuid,
+    #[schema(example = "Warehouse scanner 3")]
+    name: String,
+    /// Leading characters of the key.
+    #[schema(example = "lms_3q2-7w1x")]
+    prefix: String,
+    #[schema(example = json!(["shipment:read", "shipment:update"]))]
+    scopes: Vec<String>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    expires_at: Option<NaiveDateTime>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    last_used_at: Option<NaiveDateTime>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    revoked_at: Option<NaiveDateTime>,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: NaiveDateTime,
+}
+
+impl From<api_key::Model> for ApiKeyDTO {
+    fn from(value: api_key::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            prefix: value.prefix,
+            scopes: value.scope.split_whitespace().map(String::from).collect(),
+            expires_at: value.expires_at,
+            last_used_at: value.last_used_at,
+            revoked_at: value.revoked_at,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CreatedApiKeyDTO {
+    /// The key itself, shown only once.
+    #[schema(example = "<API_KEY>")]
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKeyDTO,
+}
+
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/api-keys",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "API keys of the caller, newest first", body = Vec<ApiKeyDTO>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn list_api_keys(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+) -> Result<Json<Vec<ApiKeyDTO>>> {
+    let keys = ApiKey::find()
+        .filter(api_key::Column::UserId.eq(claims.subject))
+        .order_by_desc(api_key::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(Json(keys.into_iter().map(ApiKeyDTO::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api-keys",
+    request_body(content = CreateApiKeyDTO, content_type = "application/json"),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 201, description = "API key created, the key is not shown again", body = CreatedApiKeyDTO),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Keys can only be created from a signed in session", body = ErrorResponse),
+        (status = 422, description = "Invalid name, scopes or lifetime", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn create_api_key(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Json(payload): Json<CreateApiKeyDTO>,
+) -> Result<(StatusCode, Json<CreatedApiKeyDTO>)> {
+    // a leaked key must not be able to mint further keys outliving its own revocation
+    if claims.api_key_id.is_some() {
+        return Err(Error::AuthorizationError);
+    }
+
+    payload.validate().map_err(Error::Garde)?;
+
+    // never more than the token creating the key carries
+    let requested: Vec<String> = payload
+        .scopes
+        .into_iter()
+        .filter(|scope| claims.scopes.contains(scope))
+        .collect();
+
+    let scopes =
+        scope::token_scopes(&state.db, claims.subject, Some(&requested.join(" ")), false).await?;
+
+    if scopes.is_empty() {
+        let mut report = garde::Report::new();
+        report.append(
+            garde::Path::new("scopes"),
+            garde::Error::new("none of the requested scopes is held"),
+        );
+        return Err(Error::Garde(report));
+    }
+
+    let now = Utc::now().naive_utc();
+    let expires_at = match payload.expires_in {
+        Some(seconds) => Some(
+            i64::try_from(seconds)
+                .ok()
+                .and_then(TimeDelta::try_seconds)
+                .and_then(|lifetime| now.checked_add_signed(lifetime))
+                .ok_or_else(|| {
+                    let mut report = garde::Report::new();
+                    report.append(
+                        garde::Path::new("expires_in"),
+                        garde::Error::new("lifetime is out of range"),
+                    );
+                    Error::Garde(report)
+                })?,
+        ),
+        None => None,
+    };
+
+    let (key, prefix) = keys::generate();
+
+    let model = api_key::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(claims.subject),
+        name: Set(payload.name),
+        prefix: Set(prefix),
+        token_hash: Set(token::hash_secret(&key)),
+        scope: Set(scopes.join(" ")),
+        expires_at: Set(expires_at),
+        last_used_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKeyDTO {
+            key,
+            api_key: model.into(),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Authentication",
+    path = "/api-keys/{id}",
+    params(("id" = Uuid, Path, description = "API key to revoke")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "API key revoked, unknown keys are ignored"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    // revoking an unknown or already revoked key is a no-op, as is one of another user
+    ApiKey::update_many()
+        .col_expr(
+            api_key::Column::RevokedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(api_key::Column::Id.eq(id))
+        .filter(api_key::Column::UserId.eq(claims.subject))
+        .filter(api_key::Column::RevokedAt.is_null())
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,16 @@
+use axum::{Json, extract::State};
+use lib_core::{AppState, error::ErrorResponse, result::Result};
+use lib_security::keys::Jwks;
+
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "Public keys verifying access tokens", body = Jwks),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn jwks(State(state): State<AppState>) -> Result<Json<Jwks>> {
+    Ok(Json(lib_security::keys::jwks(&state.keys)?))
+}
@@ -1,12 +1,77 @@
-use serde::Deserialize;
+use std::sync::LazyLock;
+
+use axum::{Form, Json, extract::State};
+use chrono::Utc;
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    metrics,
+    result::Result,
+};
+use lib_entity::{
+    prelude::{RefreshToken, Session, Users},
+    refresh_token,
+    sea_orm_active_enums::AuthEventType,
+    users,
+};
+use lib_security::{
+    auth_event::{self, Subject},
+    client::ClientInfo,
+    lockout::{self, Key},
+    password::{self, Verification},
+};
+use prometheus::IntCounterVec;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set, TransactionTrait, prelude::Uuid,
+    sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
-use lib_core::error::ErrorResponse;
 
-pub fn routes() -> OpenApiRouter<lib_core::AppState> {
+mod account;
+mod api_key;
+mod jwks;
+mod mfa;
+mod oidc;
+mod permission;
+mod session;
+mod token;
+mod unlock;
+
+/// Failed logins, by `reason`: `invalid_credentials`, or `throttled` when rejected before
+/// the password was checked.
+static LOGINS_FAILED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let counter = metrics::counter_vec(
+        "logins_failed_total",
+        "Failed logins, by reason.",
+        &["reason"],
+    );
+    // exported from zero, rates of a series appearing mid-way are lost
+    for reason in ["invalid_credentials", "throttled"] {
+        counter.with_label_values(&[reason]);
+    }
+    counter
+});
+
+pub fn routes(state: &lib_core::AppState) -> OpenApiRouter<lib_core::AppState> {
+    LazyLock::force(&LOGINS_FAILED);
+
     OpenApiRouter::new()
         .routes(routes!(login))
         .routes(routes!(refresh))
+        .merge(account::routes())
+        .merge(api_key::routes())
+        .merge(unlock::routes(state))
+        .merge(mfa::routes(state))
+        .merge(oidc::routes())
+        .merge(session::routes(state))
+        .merge(permission::routes(state))
+}
+
+/// Routes served from the root of the server instead of the `/api/v1/auth` prefix.
+pub fn well_known() -> OpenApiRouter<lib_core::AppState> {
+    OpenApiRouter::new().routes(routes!(jwks::jwks))
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -15,66 +80,330 @@ struct LoginDTO {
     email: String,
     #[schema(example = "RandomPassword1")]
     password: String,
+    /// Space delimited scopes to limit the tokens to, every held permission when omitted.
+    #[schema(example = "shipment:read shipment:update")]
+    scope: Option<String>,
 }
 
-#[derive(Debug,Deserialize,ToSchema)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct AccessTokenDTO {
     #[schema(example = "<ACCESS_TOKEN>")]
-    access_token:String,
+    access_token: String,
     #[schema(example = "<REFRESH_TOKEN>")]
-    refresh_token:String,
+    refresh_token: String,
     #[schema(example = "Bearer")]
-    token_type:String,
+    token_type: String,
     #[schema(example = 3600)]
-    exp:u16,
-    #[schema(example = json!(["service:read","service:write"]))]
-    scopes:Vec<String>
+    exp: u64,
+    #[schema(example = json!(["shipment:read","shipment:update"]))]
+    scopes: Vec<String>,
+    /// A role of the user requires a second factor, the tokens carry no scopes until one is
+    /// enrolled through `/mfa/totp`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    mfa_enrollment_required: bool,
+}
+
+/// Tokens, or a challenge to answer at `/login/mfa` when a second factor is enabled.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+enum LoginResponseDTO {
+    Token(AccessTokenDTO),
+    Challenge(mfa::MfaChallengeDTO),
+}
+
+impl AccessTokenDTO {
+    fn bearer(
+        state: &AppState,
+        access_token: String,
+        refresh_token: String,
+        scopes: Vec<String>,
+    ) -> Self {
+        Self {
+            access_token,
+            refresh_token,
+            token_type: "Bearer".into(),
+            exp: state.config.jwt.expiry,
+            scopes,
+            mfa_enrollment_required: false,
+        }
+    }
 }
 
 #[utoipa::path(
-    post, 
-    tag = "Authentication", 
+    post,
+    tag = "Authentication",
     path = "/login",
     request_body(content = LoginDTO, content_type = "application/x-www-form-urlencoded"),
     responses(
         (
-            status = 200, 
-            description = "Successfully Logged In",  
-            body = AccessTokenDTO
+            status = 200,
+            description = "Successfully Logged In, or a challenge when a second factor is enabled",
+            body = LoginResponseDTO
         ),
-        (status = 400, description = "Invalid email or password"),
-        (status = 500, description = "Internal server error")
+        (status = 401, description = "Invalid email or password", body = ErrorResponse),
+        (
+            status = 429,
+            description = "Too many failed logins for the account or address, retry after the `Retry-After` seconds",
+            body = ErrorResponse
+        ),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
-async fn login() {}
+async fn login(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Form(payload): Form<LoginDTO>,
+) -> Result<Json<LoginResponseDTO>> {
+    let keys = lockout_keys(&payload.email, &client);
+    let attempt = Subject {
+        user_id: None,
+        email: Some(&payload.email),
+    };
+
+    check_lockout(&state, &keys, attempt, &client).await?;
+
+    let user = Users::find()
+        .filter(users::Column::Email.eq(&payload.email))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
 
-#[derive(Debug,Deserialize,ToSchema)]
+    let user = match user {
+        Some(user) if verify_password(&state, &user, &payload.password).await? => user,
+        user => {
+            if user.is_none() {
+                password::verify_dummy(&state.password_params, &payload.password).await?;
+            }
+
+            let attempt = Subject {
+                user_id: user.map(|user| user.id),
+                ..attempt
+            };
+            record_failure(&state, &keys, attempt, &client).await?;
+
+            return Err(Error::AuthenticationError);
+        }
+    };
+
+    let response = sign_in(&state, user.id, payload.scope).await?;
+
+    // the login only succeeds once the second factor is answered, see `mfa::verify_challenge`
+    if let LoginResponseDTO::Token(_) = response {
+        let attempt = Subject {
+            user_id: Some(user.id),
+            ..attempt
+        };
+        record_success(&state, &keys, attempt, &client).await?;
+    }
+
+    Ok(Json(response))
+}
+
+/// Lockout counters an attempt to sign in as `email` from `client` counts against.
+fn lockout_keys(email: &str, client: &ClientInfo) -> Vec<Key> {
+    [Some(Key::account(email)), client.ip.as_deref().map(Key::ip)]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Rejects the attempt while any of `keys` is backing off or locked out.
+async fn check_lockout(
+    state: &AppState,
+    keys: &[Key],
+    attempt: Subject<'_>,
+    client: &ClientInfo,
+) -> Result<()> {
+    if let Err(err) = lockout::check(&state.db, keys).await {
+        LOGINS_FAILED.with_label_values(&["throttled"]).inc();
+        auth_event::record(&state.db, AuthEventType::Throttled, attempt, client, None).await?;
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Counts a failed attempt against each of `keys` and logs it, with the lockout it caused.
+async fn record_failure(
+    state: &AppState,
+    keys: &[Key],
+    attempt: Subject<'_>,
+    client: &ClientInfo,
+) -> Result<()> {
+    LOGINS_FAILED
+        .with_label_values(&["invalid_credentials"])
+        .inc();
+
+    let mut locked = false;
+    for key in keys.iter() {
+        locked |= lockout::record_failure(&state.db, key).await?;
+    }
+
+    auth_event::record(
+        &state.db,
+        AuthEventType::LoginFailure,
+        attempt,
+        client,
+        None,
+    )
+    .await?;
+    if locked {
+        auth_event::record(&state.db, AuthEventType::Lockout, attempt, client, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Clears the account counter among `keys` and logs the login of `attempt`.
+async fn record_success(
+    state: &AppState,
+    keys: &[Key],
+    attempt: Subject<'_>,
+    client: &ClientInfo,
+) -> Result<()> {
+    // failures from the address keep counting, they may target other accounts
+    for key in keys.iter().filter(|key| matches!(key, Key::Account(_))) {
+        lockout::reset(&state.db, key).await?;
+    }
+
+    auth_event::record(
+        &state.db,
+        AuthEventType::LoginSuccess,
+        attempt,
+        client,
+        None,
+    )
+    .await
+}
+
+/// Whether `password` signs in to the verified account `user`.
+async fn verify_password(state: &AppState, user: &users::Model, password: &str) -> Result<bool> {
+    match password::verify(&state.password_params, password, &user.password).await? {
+        Verification::Invalid => return Ok(false),
+        Verification::Valid => {}
+        Verification::NeedsRehash => {
+            // upgrade the stored hash to the current cost while the plain password is known
+            let mut model: users::ActiveModel = user.clone().into();
+            model.password = Set(password::hash(&state.password_params, password).await?);
+            model.updated_at = Set(Utc::now().naive_utc());
+            model.update(&state.db).await.map_err(Error::SeaOrm)?;
+        }
+    }
+
+    Ok(user.email_verified_at.is_some())
+}
+
+/// Completes a first factor login of `user_id`, asking for the second factor when enabled.
+async fn sign_in(
+    state: &AppState,
+    user_id: Uuid,
+    scope: Option<String>,
+) -> Result<LoginResponseDTO> {
+    if mfa::enrolled(&state.db, user_id).await? {
+        return Ok(LoginResponseDTO::Challenge(
+            mfa::challenge(&state.db, user_id, scope).await?,
+        ));
+    }
+
+    let (access_token, refresh_token, scopes) = token::start_session(state, user_id, scope).await?;
+
+    let mut tokens = AccessTokenDTO::bearer(state, access_token, refresh_token, scopes);
+    tokens.mfa_enrollment_required = mfa::enrollment_pending(&state.db, user_id).await?;
+
+    Ok(LoginResponseDTO::Token(tokens))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 struct RefreshTokenDTO {
     #[schema(example = "<REFRESH_TOKEN>")]
-    refresh_token:String,
+    refresh_token: String,
 }
 
 #[utoipa::path(
-    post, 
-    tag = "Authentication", 
+    post,
+    tag = "Authentication",
     path = "/refresh",
     request_body(content = RefreshTokenDTO, content_type = "application/json"),
     responses(
         (
-            status = 200, 
-            description = "Successfully Refreshed token",  
+            status = 200,
+            description = "Successfully Refreshed token",
             body = AccessTokenDTO
         ),
-        (   
-            status = 400, 
+        (
+            status = 401,
             description = "Invalid refresh token",
             body = ErrorResponse,
         ),
         (
-            status = 500, 
+            status = 500,
             description = "Internal server error",
             body = ErrorResponse,
         )
     )
 )]
-async fn refresh() {}
+async fn refresh(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshTokenDTO>,
+) -> Result<Json<AccessTokenDTO>> {
+    let now = Utc::now().naive_utc();
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let (current, session) = RefreshToken::find()
+        .filter(refresh_token::Column::TokenHash.eq(token::hash_secret(&payload.refresh_token)))
+        .find_also_related(Session)
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    if current.revoked_at.is_some() {
+        // a rotated token presented again means it leaked, end the whole family
+        drop(trx);
+        lib_security::revocation::revoke_session(&state, current.session_id).await?;
+        return Err(Error::AuthenticationError);
+    }
+
+    let session = match session {
+        Some(session) if current.expires_at > now && session.revoked_at.is_none() => session,
+        _ => return Err(Error::AuthenticationError),
+    };
+
+    let (next, refresh_token) = token::create_refresh_token(
+        &trx,
+        current.user_id,
+        current.session_id,
+        state.config.jwt.refresh_expiry,
+    )
+    .await?;
+
+    // rotate: the presented token can only be exchanged once, a concurrent request that
+    // already revoked it makes this update match nothing.
+    let rotated = RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(now))
+        .col_expr(refresh_token::Column::ReplacedBy, Expr::value(next.id))
+        .filter(refresh_token::Column::Id.eq(current.id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if rotated.rows_affected != 1 {
+        return Err(Error::AuthenticationError);
+    }
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    // grants changed since the last token apply now, within the scope asked for at login
+    let scopes = token::access_scopes(&state, current.user_id, session.scope.as_deref()).await?;
+    let access_token =
+        token::sign_access_token(&state, current.user_id, current.session_id, scopes.clone())?;
+
+    Ok(Json(AccessTokenDTO::bearer(
+        &state,
+        access_token,
+        refresh_token,
+        scopes,
+    )))
+}
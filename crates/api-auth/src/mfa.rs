@@ -0,0 +1,559 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    result::Result,
+};
+use lib_entity::{
+    mfa_challenge,
+    prelude::{MfaChallenge, RecoveryCode, UserTotp, Users},
+    recovery_code,
+    sea_orm_active_enums::EmployeeRole,
+    user_totp,
+};
+use lib_security::{
+    ISSUER, JWTClaim,
+    auth_event::Subject,
+    client::ClientInfo,
+    guard::{GuardedRoutes, Resource, Update},
+    role, totp,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, Set, TransactionTrait, prelude::Uuid, sea_query::Expr, sea_query::OnConflict,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{AccessTokenDTO, check_lockout, lockout_keys, record_failure, record_success, token};
+
+/// Lifetime of an MFA challenge in seconds.
+const CHALLENGE_EXPIRY: i64 = 5 * 60;
+/// Wrong codes tolerated per challenge before a new password login is needed.
+const CHALLENGE_ATTEMPTS: i32 = 5;
+
+pub struct Mfa;
+
+impl Resource for Mfa {
+    const NAME: &'static str = "mfa";
+}
+
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(verify_challenge))
+        .routes(routes!(enroll_totp))
+        .routes(routes!(activate_totp))
+        .routes(routes!(disable_totp))
+        .routes(routes!(regenerate_recovery_codes))
+        .guarded::<Mfa, Update>(state, routes!(require_role_mfa))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct MfaChallengeDTO {
+    #[schema(example = "<CHALLENGE_TOKEN>")]
+    challenge_token: String,
+    #[schema(example = "totp")]
+    challenge_type: String,
+    #[schema(example = 300)]
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ChallengeResponseDTO {
+    #[schema(example = "<CHALLENGE_TOKEN>")]
+    challenge_token: String,
+    /// Current code of the authenticator app or an unused recovery code.
+    #[schema(example = "287082")]
+    code: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct CodeDTO {
+    /// Current code of the authenticator app, recovery codes are accepted where noted.
+    #[schema(example = "287082")]
+    code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct EnrollmentDTO {
+    /// Base32 secret for apps that cannot scan the QR code.
+    #[schema(example = "JBSWY3DPEHPK3PXP")]
+    secret: String,
+    #[schema(
+        example = "otpauth://totp/logistics-management-system:johndoe%40email%2Ecom?secret=JBSWY3DPEHPK3PXP"
+    )]
+    provisioning_uri: String,
+    /// The provisioning URI as an SVG QR code.
+    qr_code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RecoveryCodesDTO {
+    /// Single use codes replacing the authenticator app, shown only once.
+    #[schema(example = json!(["K3PXP-JBSWY", "GEZDG-NBVGY"]))]
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct MfaRequirementDTO {
+    required: bool,
+}
+
+/// Whether `user_id` has an enabled authenticator app.
+pub(crate) async fn enrolled(db: &DatabaseConnection, user_id: Uuid) -> Result<bool> {
+    Ok(UserTotp::find_by_id(user_id)
+        .filter(user_totp::Column::EnabledAt.is_not_null())
+        .one(db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .is_some())
+}
+
+/// Whether `user_id` holds a role requiring a second factor but has not enrolled one yet.
+///
+/// Such accounts may sign in with their password alone to enroll, their tokens carry no
+/// scopes until they did.
+pub(crate) async fn enrollment_pending(db: &DatabaseConnection, user_id: Uuid) -> Result<bool> {
+    Ok(role::mfa_required(db, user_id).await? && !enrolled(db, user_id).await?)
+}
+
+/// Issues the challenge a password login of an enrolled account is answered with.
+pub(crate) async fn challenge(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    scope: Option<String>,
+) -> Result<MfaChallengeDTO> {
+    let secret = token::generate_secret();
+    let now = Utc::now().naive_utc();
+
+    mfa_challenge::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        token_hash: Set(token::hash_secret(&secret)),
+        scope: Set(scope),
+        attempts: Set(0),
+        expires_at: Set(now + Duration::seconds(CHALLENGE_EXPIRY)),
+        used_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok(MfaChallengeDTO {
+        challenge_token: secret,
+        challenge_type: "totp".into(),
+        exp: CHALLENGE_EXPIRY as u64,
+    })
+}
+
+/// Checks a code of the enabled authenticator app of `user_id`, or one of their unused
+/// recovery codes when `recovery` is set. Accepted codes are used up.
+async fn verify_code<C>(db: &C, user_id: Uuid, code: &str, recovery: bool) -> Result<bool>
+where
+    C: ConnectionTrait,
+{
+    let Some(enrollment) = UserTotp::find_by_id(user_id)
+        .filter(user_totp::Column::EnabledAt.is_not_null())
+        .one(db)
+        .await
+        .map_err(Error::SeaOrm)?
+    else {
+        return Ok(false);
+    };
+
+    let last_step = enrollment.last_step.map(|step| step as u64);
+    if let Some(step) = totp::verify(&enrollment.secret, code, Utc::now().timestamp(), last_step)? {
+        // a concurrent request accepting the same or a later code makes this match nothing
+        let used = UserTotp::update_many()
+            .col_expr(user_totp::Column::LastStep, Expr::value(step as i64))
+            .filter(user_totp::Column::UserId.eq(user_id))
+            .filter(
+                Condition::any()
+                    .add(user_totp::Column::LastStep.is_null())
+                    .add(user_totp::Column::LastStep.lt(step as i64)),
+            )
+            .exec(db)
+            .await
+            .map_err(Error::SeaOrm)?;
+
+        return Ok(used.rows_affected == 1);
+    }
+
+    if !recovery {
+        return Ok(false);
+    }
+
+    let used = RecoveryCode::update_many()
+        .col_expr(
+            recovery_code::Column::UsedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(
+            recovery_code::Column::CodeHash
+                .eq(token::hash_secret(&totp::normalize_recovery_code(code))),
+        )
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(used.rows_affected == 1)
+}
+
+/// Replaces every recovery code of `user_id` and returns the new ones.
+async fn replace_recovery_codes<C>(db: &C, user_id: Uuid) -> Result<Vec<String>>
+where
+    C: ConnectionTrait,
+{
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let codes = totp::recovery_codes();
+    let now = Utc::now().naive_utc();
+
+    RecoveryCode::insert_many(codes.iter().map(|code| recovery_code::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        code_hash: Set(token::hash_secret(&totp::normalize_recovery_code(code))),
+        used_at: Set(None),
+        created_at: Set(now),
+    }))
+    .exec(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok(codes)
+}
+
+/// Second factors are managed from a signed in session only, never with an API key.
+fn interactive(claims: &JWTClaim) -> Result<()> {
+    if claims.api_key_id.is_some() {
+        return Err(Error::AuthorizationError);
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/login/mfa",
+    request_body(content = ChallengeResponseDTO, content_type = "application/json"),
+    responses(
+        (status = 200, description = "Second factor accepted", body = AccessTokenDTO),
+        (status = 401, description = "Invalid, expired or exhausted challenge or wrong code", body = ErrorResponse),
+        (
+            status = 429,
+            description = "Too many failed logins for the account or address, retry after the `Retry-After` seconds",
+            body = ErrorResponse
+        ),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn verify_challenge(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<ChallengeResponseDTO>,
+) -> Result<Json<AccessTokenDTO>> {
+    let now = Utc::now().naive_utc();
+
+    let challenge = MfaChallenge::find()
+        .filter(mfa_challenge::Column::TokenHash.eq(token::hash_secret(&payload.challenge_token)))
+        .filter(mfa_challenge::Column::UsedAt.is_null())
+        .filter(mfa_challenge::Column::ExpiresAt.gt(now))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    let user = Users::find_by_id(challenge.user_id)
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    // wrong codes count against the same counters as wrong passwords
+    let keys = lockout_keys(&user.email, &client);
+    let attempt = Subject {
+        user_id: Some(user.id),
+        email: Some(&user.email),
+    };
+
+    check_lockout(&state, &keys, attempt, &client).await?;
+
+    // counted before checking the code so parallel guesses cannot exceed the limit
+    let counted = MfaChallenge::update_many()
+        .col_expr(
+            mfa_challenge::Column::Attempts,
+            Expr::col(mfa_challenge::Column::Attempts).add(1),
+        )
+        .filter(mfa_challenge::Column::Id.eq(challenge.id))
+        .filter(mfa_challenge::Column::Attempts.lt(CHALLENGE_ATTEMPTS))
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if counted.rows_affected != 1 {
+        return Err(Error::AuthenticationError);
+    }
+
+    if !verify_code(&state.db, challenge.user_id, &payload.code, true).await? {
+        record_failure(&state, &keys, attempt, &client).await?;
+        return Err(Error::AuthenticationError);
+    }
+
+    let used = MfaChallenge::update_many()
+        .col_expr(mfa_challenge::Column::UsedAt, Expr::value(now))
+        .filter(mfa_challenge::Column::Id.eq(challenge.id))
+        .filter(mfa_challenge::Column::UsedAt.is_null())
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if used.rows_affected != 1 {
+        return Err(Error::AuthenticationError);
+    }
+
+    let (access_token, refresh_token, scopes) =
+        token::start_session(&state, challenge.user_id, challenge.scope).await?;
+
+    record_success(&state, &keys, attempt, &client).await?;
+
+    Ok(Json(AccessTokenDTO::bearer(
+        &state,
+        access_token,
+        refresh_token,
+        scopes,
+    )))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/mfa/totp",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Secret to add to an authenticator app, enabled by the first code", body = EnrollmentDTO),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "An authenticator app is already enabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn enroll_totp(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+) -> Result<Json<EnrollmentDTO>> {
+    interactive(&claims)?;
+
+    if enrolled(&state.db, claims.subject).await? {
+        return Err(Error::AuthorizationError);
+    }
+
+    let user = Users::find_by_id(claims.subject)
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    let secret = totp::generate_secret();
+
+    // restarting an unfinished enrollment replaces its secret
+    UserTotp::insert(user_totp::ActiveModel {
+        user_id: Set(user.id),
+        secret: Set(secret.clone()),
+        last_step: Set(None),
+        enabled_at: Set(None),
+        created_at: Set(Utc::now().naive_utc()),
+    })
+    .on_conflict(
+        OnConflict::column(user_totp::Column::UserId)
+            .update_columns([user_totp::Column::Secret, user_totp::Column::CreatedAt])
+            .action_and_where(user_totp::Column::EnabledAt.is_null())
+            .to_owned(),
+    )
+    .exec_without_returning(&state.db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    let provisioning_uri = totp::provisioning_uri(&secret, ISSUER, &user.email);
+
+    Ok(Json(EnrollmentDTO {
+        qr_code: api_qr::svg(&provisioning_uri)?,
+        secret,
+        provisioning_uri,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/mfa/totp/activate",
+    request_body(content = CodeDTO, content_type = "application/json"),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Authenticator app enabled", body = RecoveryCodesDTO),
+        (status = 401, description = "Missing or invalid access token or wrong code", body = ErrorResponse),
+        (status = 403, description = "No enrollment was started", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn activate_totp(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Json(payload): Json<CodeDTO>,
+) -> Result<Json<RecoveryCodesDTO>> {
+    interactive(&claims)?;
+
+    let enrollment = UserTotp::find_by_id(claims.subject)
+        .filter(user_totp::Column::EnabledAt.is_null())
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthorizationError)?;
+
+    let step = totp::verify(
+        &enrollment.secret,
+        &payload.code,
+        Utc::now().timestamp(),
+        None,
+    )?
+    .ok_or(Error::AuthenticationError)?;
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let enabled = UserTotp::update_many()
+        .col_expr(
+            user_totp::Column::EnabledAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .col_expr(user_totp::Column::LastStep, Expr::value(step as i64))
+        .filter(user_totp::Column::UserId.eq(claims.subject))
+        .filter(user_totp::Column::Secret.eq(enrollment.secret))
+        .filter(user_totp::Column::EnabledAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if enabled.rows_affected != 1 {
+        return Err(Error::AuthorizationError);
+    }
+
+    let recovery_codes = replace_recovery_codes(&trx, claims.subject).await?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(Json(RecoveryCodesDTO { recovery_codes }))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/mfa/totp/disable",
+    request_body(content = CodeDTO, content_type = "application/json"),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Authenticator app and recovery codes removed, recovery codes are accepted"),
+        (status = 401, description = "Missing or invalid access token or wrong code", body = ErrorResponse),
+        (status = 403, description = "A role of the user requires a second factor", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn disable_totp(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Json(payload): Json<CodeDTO>,
+) -> Result<StatusCode> {
+    interactive(&claims)?;
+
+    if role::mfa_required(&state.db, claims.subject).await? {
+        return Err(Error::AuthorizationError);
+    }
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    if !verify_code(&trx, claims.subject, &payload.code, true).await? {
+        return Err(Error::AuthenticationError);
+    }
+
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::UserId.eq(claims.subject))
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    UserTotp::delete_by_id(claims.subject)
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/mfa/recovery-codes",
+    request_body(content = CodeDTO, content_type = "application/json"),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Previous recovery codes replaced", body = RecoveryCodesDTO),
+        (status = 401, description = "Missing or invalid access token or wrong code", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Json(payload): Json<CodeDTO>,
+) -> Result<Json<RecoveryCodesDTO>> {
+    interactive(&claims)?;
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    if !verify_code(&trx, claims.subject, &payload.code, false).await? {
+        return Err(Error::AuthenticationError);
+    }
+
+    let recovery_codes = replace_recovery_codes(&trx, claims.subject).await?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(Json(RecoveryCodesDTO { recovery_codes }))
+}
+
+#[utoipa::path(
+    put,
+    tag = "Authentication",
+    path = "/mfa/roles/{role}",
+    params(("role" = String, Path, description = "Employee role, e.g. Admin")),
+    request_body(content = MfaRequirementDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Requirement updated, it also binds roles extending this one"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing mfa update permission", body = ErrorResponse),
+        (status = 404, description = "No role has this name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn require_role_mfa(
+    State(state): State<AppState>,
+    Path(employee_role): Path<EmployeeRole>,
+    Json(payload): Json<MfaRequirementDTO>,
+) -> Result<StatusCode> {
+    role::require_mfa(&state.db, employee_role, payload.required).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,540 @@
+use std::time::Duration as StdDuration;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::Redirect,
+};
+use chrono::{Duration, Utc};
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    oidc::OidcProvider,
+    result::Result,
+};
+use lib_entity::{
+    extensions::UNUSABLE_PASSWORD,
+    oidc_login,
+    prelude::{OidcLogin, UserIdentity, Users},
+    sea_orm_active_enums::AuthType,
+    user_identity, users,
+};
+use lib_security::{
+    auth_event::Subject,
+    client::ClientInfo,
+    lockout::Key,
+    oidc::{self, IdToken},
+    revocation,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set, TransactionTrait, prelude::Uuid,
+    sea_query::Expr,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{LoginResponseDTO, check_lockout, record_failure, record_success, sign_in, token};
+
+/// Time the user has to sign in at the provider, in seconds.
+const LOGIN_EXPIRY: i64 = 10 * 60;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(authorize))
+        .routes(routes!(callback))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuthorizeQuery {
+    /// Space delimited scopes to limit the tokens to, every held permission when omitted.
+    scope: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: String,
+    /// Set by the provider instead of `code` when the user did not sign in.
+    error: Option<String>,
+}
+
+fn provider(state: &AppState) -> Result<&OidcProvider> {
+    state
+        .config
+        .oidc
+        .as_ref()
+        .ok_or_else(|| Error::Custom("OpenID Connect provider is not configured".into()))
+}
+
+/// Finds the account linked to the provider identity, linking or creating one on first
+/// sign in.
+///
+/// Existing accounts are only linked through an email address the provider verified,
+/// otherwise anyone registering the address at the provider could take them over.
+/// An existing account whose address was never verified loses its password and every
+/// credential, they were set up by whoever registered the address first.
+async fn link(state: &AppState, id_token: IdToken) -> Result<Uuid> {
+    let now = Utc::now().naive_utc();
+
+    let identity = UserIdentity::find()
+        .filter(user_identity::Column::Issuer.eq(&id_token.issuer))
+        .filter(user_identity::Column::Subject.eq(&id_token.subject))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if let Some(identity) = identity {
+        UserIdentity::update_many()
+            .col_expr(user_identity::Column::LastLoginAt, Expr::value(now))
+            .filter(user_identity::Column::Id.eq(identity.id))
+            .exec(&state.db)
+            .await
+            .map_err(Error::SeaOrm)?;
+
+        return Ok(identity.user_id);
+    }
+
+    let email = match id_token.email {
+        Some(email) if id_token.email_verified => email,
+        _ => return Err(Error::AuthenticationError),
+    };
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let existing = Users::find()
+        .filter(users::Column::Email.eq(&email))
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let user_id = match existing {
+        Some(user) => {
+            // whoever registered the unverified address chose its password, which must not
+            // keep opening the account the provider just proved belongs to someone else
+            if user.email_verified_at.is_none() {
+                let mut model: users::ActiveModel = user.clone().into();
+                model.email_verified_at = Set(Some(now));
+                model.password = Set(UNUSABLE_PASSWORD.into());
+                model.updated_at = Set(now);
+                model.update(&trx).await.map_err(Error::SeaOrm)?;
+
+                revocation::revoke_user_credentials(&trx, user.id).await?;
+            }
+
+            user.id
+        }
+        None => {
+            users::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                auth_type: Set(AuthType::Oidc),
+                email: Set(email.clone()),
+                password: Set(UNUSABLE_PASSWORD.into()),
+                email_verified_at: Set(Some(now)),
+                ..Default::default()
+            }
+            .insert(&trx)
+            .await
+            .map_err(Error::SeaOrm)?
+            .id
+        }
+    };
+
+    user_identity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        issuer: Set(id_token.issuer),
+        subject: Set(id_token.subject),
+        email: Set(Some(email)),
+        created_at: Set(now),
+        last_login_at: Set(Some(now)),
+    }
+    .insert(&trx)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(user_id)
+}
+
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/oidc/authorize",
+    params(AuthorizeQuery),
+    responses(
+        (status = 303, description = "Redirect to the sign in page of the provider"),
+        (status = 500, description = "Internal server error or no provider configured", body = ErrorResponse)
+    )
+)]
+async fn authorize(
+    State(state): State<AppState>,
+    Query(query): Query<AuthorizeQuery>,
+) -> Result<Redirect> {
+    let provider = provider(&state)?;
+    let metadata = oidc::metadata(provider, &state.oidc_discovery).await?;
+
+    // requests abandoned at the provider are never answered
+    OidcLogin::delete_many()
+        .filter(oidc_login::Column::ExpiresAt.lte(Utc::now().naive_utc()))
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let login_state = token::generate_secret();
+    let nonce = token::generate_secret();
+    let (verifier, challenge) = oidc::pkce();
+    let now = Utc::now().naive_utc();
+
+    oidc_login::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        state_hash: Set(token::hash_secret(&login_state)),
+        nonce: Set(nonce.clone()),
+        code_verifier: Set(verifier),
+        scope: Set(query.scope),
+        expires_at: Set(now + Duration::seconds(LOGIN_EXPIRY)),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok(Redirect::to(&oidc::authorization_url(
+        provider,
+        &metadata,
+        &login_state,
+        &nonce,
+        &challenge,
+    )?))
+}
+
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/oidc/callback",
+    params(CallbackQuery),
+    responses(
+        (
+            status = 200,
+            description = "Signed in through the provider, or a challenge when a second factor is enabled",
+            body = LoginResponseDTO
+        ),
+        (status = 401, description = "Unknown or expired state, rejected code or unverified email", body = ErrorResponse),
+        (
+            status = 429,
+            description = "Too many failed logins from the address, retry after the `Retry-After` seconds",
+            body = ErrorResponse
+        ),
+        (status = 500, description = "Internal server error or no provider configured", body = ErrorResponse)
+    )
+)]
+async fn callback(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Json<LoginResponseDTO>> {
+    let provider = provider(&state)?;
+
+    // the account is unknown until the provider answers, only the address is throttled
+    let keys: Vec<Key> = client.ip.as_deref().map(Key::ip).into_iter().collect();
+    let attempt = Subject {
+        user_id: None,
+        email: None,
+    };
+
+    check_lockout(&state, &keys, attempt, &client).await?;
+
+    let (user_id, email, scope) = match authenticate(&state, provider, query).await {
+        Ok(signed_in) => signed_in,
+        Err(Error::AuthenticationError) => {
+            record_failure(&state, &keys, attempt, &client).await?;
+            return Err(Error::AuthenticationError);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let response = sign_in(&state, user_id, scope).await?;
+
+    if let LoginResponseDTO::Token(_) = response {
+        let attempt = Subject {
+            user_id: Some(user_id),
+            email: email.as_deref(),
+        };
+        record_success(&state, &keys, attempt, &client).await?;
+    }
+
+    Ok(Json(response))
+}
+
+/// Redeems the answer of the provider for the linked account, its email at the provider
+/// and the scope of the authorization request.
+async fn authenticate(
+    state: &AppState,
+    provider: &OidcProvider,
+    query: CallbackQuery,
+) -> Result<(Uuid, Option<String>, Option<String>)> {
+    let login = OidcLogin::find()
+        .filter(oidc_login::Column::StateHash.eq(token::hash_secret(&query.state)))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    // every authorization request is answered at most once
+    let consumed = OidcLogin::delete_by_id(login.id)
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if consumed.rows_affected != 1 || login.expires_at <= Utc::now().naive_utc() {
+        return Err(Error::AuthenticationError);
+    }
+
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        _ => return Err(Error::AuthenticationError),
+    };
+
+    let metadata = oidc::metadata(provider, &state.oidc_discovery).await?;
+    let id_token = oidc::authenticate(
+        provider,
+        &metadata,
+        &code,
+        &login.code_verifier,
+        &login.nonce,
+        StdDuration::from_secs(state.config.jwt.leeway),
+    )
+    .await?;
+
+    let email = id_token.email.clone();
+    let user_id = link(state, id_token).await?;
+
+    Ok((user_id, email, login.scope))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        Router,
+        body::Body,
+        extract::Request,
+        http::{StatusCode, header},
+        routing,
+    };
+    use jwt::{AlgorithmType, Header, PKeyWithDigest, SignWithKey, Token};
+    use lib_core::{
+        config::Config,
+        keys::{Key, KeyRing},
+        mailer::LogMailer,
+    };
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa};
+    use sea_orm::{ConnectOptions, Database};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+    use url::Url;
+
+    use super::*;
+
+    const CLIENT_ID: &str = "logistics";
+
+    fn key(kid: &str) -> Key {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let public_key =
+            PKey::public_key_from_der(&private_key.public_key_to_der().unwrap()).unwrap();
+
+        Key {
+            kid: kid.into(),
+            private_key,
+            public_key,
+        }
+    }
+
+    /// Provider answering every code with an ID token for `email`, bound to the nonce of
+    /// the last authorization request.
+    #[derive(Clone)]
+    struct Issuer {
+        url: String,
+        key: Key,
+        email: String,
+        nonce: Arc<Mutex<String>>,
+    }
+
+    impl Issuer {
+        fn id_token(&self) -> String {
+            let header = Header {
+                algorithm: AlgorithmType::Rs256,
+                key_id: Some(self.key.kid.clone()),
+                ..Default::default()
+            };
+            let claims = json!({
+                "iss": self.url,
+                "sub": Uuid::new_v4().to_string(),
+                "aud": CLIENT_ID,
+                "exp": Utc::now().timestamp() + 300,
+                "iat": Utc::now().timestamp(),
+                "nonce": *self.nonce.lock().unwrap(),
+                "email": self.email,
+                "email_verified": true,
+            });
+
+            Token::new(header, claims)
+                .sign_with_key(&PKeyWithDigest {
+                    digest: MessageDigest::sha256(),
+                    key: self.key.private_key.clone(),
+                })
+                .unwrap()
+                .as_str()
+                .to_string()
+        }
+    }
+
+    async fn mock_issuer(email: &str) -> Issuer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = Issuer {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            key: key("provider"),
+            email: email.into(),
+            nonce: Arc::default(),
+        };
+
+        let discovery = json!({
+            "issuer": issuer.url,
+            "authorization_endpoint": format!("{}/authorize", issuer.url),
+            "token_endpoint": format!("{}/token", issuer.url),
+            "jwks_uri": format!("{}/jwks", issuer.url),
+        });
+        let ring = KeyRing::default();
+        ring.replace(vec![issuer.key.clone()]);
+        let jwks = serde_json::to_value(lib_security::keys::jwks(&ring).unwrap()).unwrap();
+        let token = issuer.clone();
+
+        let router = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                routing::get(move || async move { Json(discovery) }),
+            )
+            .route("/jwks", routing::get(move || async move { Json(jwks) }))
+            .route(
+                "/token",
+                routing::post(move || async move {
+                    Json(json!({
+                        "access_token": "provider-access-token",
+                        "token_type": "Bearer",
+                        "id_token": token.id_token(),
+                    }))
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        issuer
+    }
+
+    async fn state(issuer: &Issuer) -> AppState {
+        let url = std::env::var("RUST_DATABASE_URL").unwrap();
+        let mut options = ConnectOptions::new(url);
+        options.set_schema_search_path("logistics,public");
+        let db = Database::connect(options).await.unwrap();
+
+        let config = Config {
+            oidc: Some(OidcProvider {
+                issuer: issuer.url.clone(),
+                client_id: CLIENT_ID.into(),
+                client_secret: None,
+                redirect_uri: "http://localhost:8000/api/v1/auth/oidc/callback".into(),
+            }),
+            ..Default::default()
+        };
+
+        let keys = KeyRing::default();
+        keys.replace(vec![key("server")]);
+
+        AppState {
+            config: Arc::new(config),
+            db,
+            keys: Arc::new(keys),
+            revocations: Arc::default(),
+            password_params: argon2::Params::default(),
+            mailer: Arc::new(LogMailer),
+            oidc_discovery: Arc::default(),
+            shutdown: Default::default(),
+        }
+    }
+
+    async fn send(state: &AppState, request: Request) -> axum::response::Response {
+        let (router, _) = crate::routes(state).split_for_parts();
+        router
+            .with_state(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    async fn login(state: &AppState, email: &str, password: &str) -> StatusCode {
+        let request = Request::post("/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!(
+                "email={}&password={password}",
+                email.replace('@', "%40")
+            )))
+            .unwrap();
+
+        send(state, request).await.status()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated Postgres database of RUST_DATABASE_URL"]
+    async fn linking_an_unverified_account_discards_its_password() {
+        let email = format!("{}@email.com", Uuid::new_v4());
+        let password = "RandomPassword1!";
+        let issuer = mock_issuer(&email).await;
+        let state = state(&issuer).await;
+
+        // someone registers the address first and never verifies it
+        let register = Request::post("/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "email": email, "password": password }).to_string(),
+            ))
+            .unwrap();
+        assert_eq!(send(&state, register).await.status(), StatusCode::ACCEPTED);
+
+        let authorize = send(
+            &state,
+            Request::get("/oidc/authorize").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(authorize.status(), StatusCode::SEE_OTHER);
+        let location = authorize.headers()[header::LOCATION].to_str().unwrap();
+        let query: HashMap<_, _> = Url::parse(location)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        *issuer.nonce.lock().unwrap() = query["nonce"].clone();
+
+        let callback = Request::get(format!("/oidc/callback?code=code&state={}", query["state"]))
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&state, callback).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["access_token"].is_string(), "{body}");
+
+        // the address is verified now, only the discarded password keeps the account shut
+        assert_eq!(
+            login(&state, &email, password).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -0,0 +1,445 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use lib_core::{AppState, error::ErrorResponse, result::Result};
+use lib_entity::{permission_audit, permissions, role, role_permission};
+use lib_security::{
+    JWTClaim, Permission, Scope, audit,
+    guard::{Create, Delete, GuardedRoutes, Read, Resource},
+    role as roles,
+};
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// Upper bound of audit entries returned at once.
+const AUDIT_LIMIT: u64 = 500;
+
+pub struct Permissions;
+
+impl Resource for Permissions {
+    const NAME: &'static str = "permissions";
+}
+
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .guarded::<Permissions, Read>(state, routes!(user_permissions))
+        .guarded::<Permissions, Create>(state, routes!(grant_user_permission))
+        .guarded::<Permissions, Delete>(state, routes!(revoke_user_permission))
+        .guarded::<Permissions, Create>(state, routes!(assign_role))
+        .guarded::<Permissions, Delete>(state, routes!(unassign_role))
+        .guarded::<Permissions, Read>(state, routes!(list_roles))
+        .guarded::<Permissions, Read>(state, routes!(role_permissions))
+        .guarded::<Permissions, Create>(state, routes!(grant_role_permission))
+        .guarded::<Permissions, Delete>(state, routes!(revoke_role_permission))
+        .guarded::<Permissions, Read>(state, routes!(audit_trail))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RoleDTO {
+    id: Uuid,
+    #[schema(example = "Manager")]
+    name: String,
+    /// Role whose permissions are inherited.
+    parent_id: Option<Uuid>,
+    /// Holders must enroll a second factor, also applies to roles extending this one.
+    mfa_required: bool,
+}
+
+impl From<role::Model> for RoleDTO {
+    fn from(value: role::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            parent_id: value.parent_id,
+            mfa_required: value.mfa_required,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct GrantDTO {
+    id: Uuid,
+    #[schema(example = "shipment")]
+    entity_name: String,
+    #[schema(value_type = String, example = "read")]
+    action: Permission,
+    scope: Scope,
+    granted_by: Option<Uuid>,
+    #[schema(value_type = String, format = DateTime)]
+    granted_at: NaiveDateTime,
+}
+
+impl From<permissions::Model> for GrantDTO {
+    fn from(value: permissions::Model) -> Self {
+        Self {
+            id: value.id,
+            entity_name: value.entity_name,
+            action: value.action,
+            scope: Scope::from_columns(value.resource_column, value.resource_value),
+            granted_by: value.granted_by,
+            granted_at: value.granted_at,
+        }
+    }
+}
+
+impl From<role_permission::Model> for GrantDTO {
+    fn from(value: role_permission::Model) -> Self {
+        Self {
+            id: value.id,
+            entity_name: value.entity_name,
+            action: value.action,
+            scope: Scope::from_columns(value.resource_column, value.resource_value),
+            granted_by: value.granted_by,
+            granted_at: value.granted_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct UserPermissionsDTO {
+    roles: Vec<RoleDTO>,
+    grants: Vec<GrantDTO>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct GrantRequestDTO {
+    #[schema(example = "shipment")]
+    entity_name: String,
+    #[schema(value_type = Vec<String>, example = json!(["read", "update"]))]
+    actions: Vec<Permission>,
+    #[serde(default)]
+    scope: Scope,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct AuditDTO {
+    id: Uuid,
+    #[schema(value_type = String, example = "grant")]
+    event: lib_entity::sea_orm_active_enums::PermissionEvent,
+    actor_id: Option<Uuid>,
+    user_id: Option<Uuid>,
+    role_id: Option<Uuid>,
+    #[schema(example = "shipment")]
+    entity_name: Option<String>,
+    #[schema(value_type = Option<String>, example = "read")]
+    action: Option<Permission>,
+    scope: Option<Scope>,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: NaiveDateTime,
+}
+
+impl From<permission_audit::Model> for AuditDTO {
+    fn from(value: permission_audit::Model) -> Self {
+        Self {
+            id: value.id,
+            event: value.event,
+            actor_id: value.actor_id,
+            user_id: value.user_id,
+            role_id: value.role_id,
+            scope: value
+                .entity_name
+                .is_some()
+                .then(|| Scope::from_columns(value.resource_column, value.resource_value)),
+            entity_name: value.entity_name,
+            action: value.action,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuditQuery {
+    user_id: Option<Uuid>,
+    role_id: Option<Uuid>,
+    /// Defaults to and is capped at 500.
+    limit: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    tag = "Permissions",
+    path = "/users/{id}/permissions",
+    params(("id" = Uuid, Path, description = "User whose grants are listed")),
+    responses(
+        (status = 200, description = "Roles and direct grants of the user", body = UserPermissionsDTO),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions read permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn user_permissions(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<UserPermissionsDTO>> {
+    Ok(Json(UserPermissionsDTO {
+        roles: roles::user_roles(&state.db, id)
+            .await?
+            .into_iter()
+            .map(RoleDTO::from)
+            .collect(),
+        grants: lib_security::user_permissions(&state.db, id)
+            .await?
+            .into_iter()
+            .map(GrantDTO::from)
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Permissions",
+    path = "/users/{id}/permissions",
+    params(("id" = Uuid, Path, description = "User receiving the grant")),
+    request_body(content = GrantRequestDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Permissions granted"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions create permission", body = ErrorResponse),
+        (status = 422, description = "Scope naming a column the table does not have", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn grant_user_permission(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<GrantRequestDTO>,
+) -> Result<StatusCode> {
+    lib_security::grant_permission(
+        &state.db,
+        &claims,
+        id,
+        &payload.entity_name,
+        payload.actions,
+        &payload.scope,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Permissions",
+    path = "/users/{id}/permissions",
+    params(("id" = Uuid, Path, description = "User losing the grant")),
+    request_body(content = GrantRequestDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Permissions revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions delete permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn revoke_user_permission(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<GrantRequestDTO>,
+) -> Result<StatusCode> {
+    lib_security::revoke_permission(
+        &state.db,
+        &claims,
+        id,
+        &payload.entity_name,
+        payload.actions,
+        &payload.scope,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    put,
+    tag = "Permissions",
+    path = "/users/{id}/roles/{role_id}",
+    params(
+        ("id" = Uuid, Path, description = "User receiving the role"),
+        ("role_id" = Uuid, Path, description = "Assigned role")
+    ),
+    responses(
+        (status = 204, description = "Role assigned"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions create permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn assign_role(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode> {
+    roles::assign_role(&state.db, &claims, id, role_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Permissions",
+    path = "/users/{id}/roles/{role_id}",
+    params(
+        ("id" = Uuid, Path, description = "User losing the role"),
+        ("role_id" = Uuid, Path, description = "Unassigned role")
+    ),
+    responses(
+        (status = 204, description = "Role unassigned"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions delete permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn unassign_role(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode> {
+    roles::unassign_role(&state.db, &claims, id, role_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    tag = "Permissions",
+    path = "/roles",
+    responses(
+        (status = 200, description = "Every role", body = Vec<RoleDTO>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions read permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn list_roles(State(state): State<AppState>) -> Result<Json<Vec<RoleDTO>>> {
+    Ok(Json(
+        roles::roles(&state.db)
+            .await?
+            .into_iter()
+            .map(RoleDTO::from)
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    tag = "Permissions",
+    path = "/roles/{id}/permissions",
+    params(("id" = Uuid, Path, description = "Role whose grants are listed")),
+    responses(
+        (status = 200, description = "Grants of the role, without inherited ones", body = Vec<GrantDTO>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions read permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn role_permissions(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<GrantDTO>>> {
+    Ok(Json(
+        roles::role_permissions(&state.db, id)
+            .await?
+            .into_iter()
+            .map(GrantDTO::from)
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Permissions",
+    path = "/roles/{id}/permissions",
+    params(("id" = Uuid, Path, description = "Role receiving the grant")),
+    request_body(content = GrantRequestDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Permissions granted"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions create permission", body = ErrorResponse),
+        (status = 422, description = "Scope naming a column the table does not have", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn grant_role_permission(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<GrantRequestDTO>,
+) -> Result<StatusCode> {
+    roles::grant_role_permission(
+        &state.db,
+        &claims,
+        id,
+        &payload.entity_name,
+        payload.actions,
+        &payload.scope,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Permissions",
+    path = "/roles/{id}/permissions",
+    params(("id" = Uuid, Path, description = "Role losing the grant")),
+    request_body(content = GrantRequestDTO, content_type = "application/json"),
+    responses(
+        (status = 204, description = "Permissions revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions delete permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn revoke_role_permission(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<GrantRequestDTO>,
+) -> Result<StatusCode> {
+    roles::revoke_role_permission(
+        &state.db,
+        &claims,
+        id,
+        &payload.entity_name,
+        payload.actions,
+        &payload.scope,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    tag = "Permissions",
+    path = "/permissions/audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Permission changes, most recent first", body = Vec<AuditDTO>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing permissions read permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn audit_trail(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditDTO>>> {
+    let limit = query.limit.unwrap_or(AUDIT_LIMIT).min(AUDIT_LIMIT);
+
+    Ok(Json(
+        audit::history(&state.db, query.user_id, query.role_id, limit)
+            .await?
+            .into_iter()
+            .map(AuditDTO::from)
+            .collect(),
+    ))
+}
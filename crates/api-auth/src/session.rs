@@ -0,0 +1,66 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use lib_core::{AppState, error::ErrorResponse, result::Result};
+use lib_security::{
+    JWTClaim,
+    guard::{Delete, GuardedRoutes, Resource},
+    revocation,
+};
+use sea_orm::prelude::Uuid;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+pub struct Session;
+
+impl Resource for Session {
+    const NAME: &'static str = "session";
+}
+
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(logout))
+        .guarded::<Session, Delete>(state, routes!(revoke_user_sessions))
+}
+
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/logout",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Access token and its session revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn logout(State(state): State<AppState>, claims: JWTClaim) -> Result<StatusCode> {
+    revocation::revoke_token(&state, &claims).await?;
+
+    if let Some(session_id) = claims.session_id {
+        revocation::revoke_session(&state, session_id).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Authentication",
+    path = "/users/{id}/sessions",
+    params(("id" = Uuid, Path, description = "User whose sessions are revoked")),
+    responses(
+        (status = 204, description = "Every session of the user revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing session delete permission", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn revoke_user_sessions(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    revocation::revoke_user_sessions(&state, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use lib_core::{AppState, error::Error, result::Result};
+use lib_entity::{refresh_token, session};
+pub use lib_security::secret::{generate_secret, hash_secret};
+use lib_security::{JWTClaim, scope};
+use sea_orm::{ActiveModelTrait, ConnectionTrait, Set, TransactionTrait, prelude::Uuid};
+
+use crate::mfa;
+
+/// Scopes of an access token of `user_id`, limited to the `requested` ones of its session.
+///
+/// Accounts that still have to enroll a required second factor get none.
+pub async fn access_scopes(
+    state: &AppState,
+    user_id: Uuid,
+    requested: Option<&str>,
+) -> Result<Vec<String>> {
+    if mfa::enrollment_pending(&state.db, user_id).await? {
+        return Ok(vec![]);
+    }
+
+    scope::token_scopes(
+        &state.db,
+        user_id,
+        requested,
+        state.config.jwt.scope_authorization,
+    )
+    .await
+}
+
+/// Signs a short lived access token for `user_id` with the current signing key.
+pub fn sign_access_token(
+    state: &AppState,
+    user_id: Uuid,
+    session_id: Uuid,
+    scopes: Vec<String>,
+) -> Result<String> {
+    let mut claims = JWTClaim::issue(
+        user_id,
+        scopes,
+        Duration::from_secs(state.config.jwt.expiry),
+    );
+    claims.session_id = Some(session_id);
+    claims.sign(&state.keys)
+}
+
+/// Starts a new session for `user_id` limited to the `requested` scopes and returns its
+/// first access and refresh tokens together with the granted scopes.
+pub async fn start_session(
+    state: &AppState,
+    user_id: Uuid,
+    requested: Option<String>,
+) -> Result<(String, String, Vec<String>)> {
+    let scopes = access_scopes(state, user_id, requested.as_deref()).await?;
+
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    let session = session::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        created_at: Set(Utc::now().naive_utc()),
+        revoked_at: Set(None),
+        scope: Set(requested),
+    }
+    .insert(&trx)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    let (_, refresh_token) =
+        create_refresh_token(&trx, user_id, session.id, state.config.jwt.refresh_expiry).await?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok((
+        sign_access_token(state, user_id, session.id, scopes.clone())?,
+        refresh_token,
+        scopes,
+    ))
+}
+
+/// Generates a new refresh token of the session and persists its hash.
+///
+/// Returns the stored row together with the plain token that must be handed to the client.
+pub async fn create_refresh_token<C>(
+    db: &C,
+    user_id: Uuid,
+    session_id: Uuid,
+    ttl: u64,
+) -> Result<(refresh_token::Model, String)>
+where
+    C: ConnectionTrait,
+{
+    let token = generate_secret();
+    let now = Utc::now().naive_utc();
+
+    let model = refresh_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        token_hash: Set(hash_secret(&token)),
+        expires_at: Set(now + TimeDelta::seconds(ttl as i64)),
+        revoked_at: Set(None),
+        replaced_by: Set(None),
+        created_at: Set(now),
+        session_id: Set(session_id),
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok((model, token))
+}
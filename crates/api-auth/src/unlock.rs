@@ -0,0 +1,72 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use lib_core::{
+    AppState,
+    error::{Error, ErrorResponse},
+    result::Result,
+};
+use lib_entity::{prelude::Users, sea_orm_active_enums::AuthEventType};
+use lib_security::{
+    JWTClaim,
+    auth_event::{self, Subject},
+    client::ClientInfo,
+    guard::{Delete, GuardedRoutes, Resource},
+    lockout::{self, Key},
+};
+use sea_orm::{EntityTrait, prelude::Uuid};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+pub struct Lockout;
+
+impl Resource for Lockout {
+    const NAME: &'static str = "lockout";
+}
+
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().guarded::<Lockout, Delete>(state, routes!(unlock))
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Authentication",
+    path = "/users/{id}/lockout",
+    params(("id" = Uuid, Path, description = "User whose account is unlocked")),
+    responses(
+        (status = 204, description = "Failed logins of the account forgotten and its lockout lifted"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Missing lockout delete permission", body = ErrorResponse),
+        (status = 404, description = "Unknown user", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+async fn unlock(
+    State(state): State<AppState>,
+    claims: JWTClaim,
+    client: ClientInfo,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    let user = Users::find_by_id(id)
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::RowNotFound)?;
+
+    // failures from addresses are left alone, they may target other accounts
+    if lockout::reset(&state.db, &Key::account(&user.email)).await? {
+        auth_event::record(
+            &state.db,
+            AuthEventType::Unlock,
+            Subject {
+                user_id: Some(user.id),
+                email: Some(&user.email),
+            },
+            &client,
+            Some(claims.subject),
+        )
+        .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
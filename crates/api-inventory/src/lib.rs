@@ -1,11 +1,14 @@
 use lib_core::AppState;
 use utoipa_axum::router::OpenApiRouter;
 
+pub mod metrics;
 pub mod shipment;
 pub mod warehouse;
 
-pub fn routes() -> OpenApiRouter<AppState> {
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
+    metrics::register();
+
     OpenApiRouter::new()
-        .nest("/shipment", shipment::routes())
-        .nest("/warehouse", warehouse::routes())
+        .nest("/shipment", shipment::routes(state))
+        .nest("/warehouse", warehouse::routes(state))
 }
@@ -0,0 +1,30 @@
+//! Inventory counters, registered up front so dashboards see them from zero. The
+//! shipment and warehouse handlers increment them once they are implemented.
+
+use std::sync::LazyLock;
+
+use lib_core::metrics;
+use prometheus::{IntCounter, IntCounterVec};
+
+/// Shipments created, incremented once the shipment is committed.
+pub static SHIPMENTS_CREATED: LazyLock<IntCounter> =
+    LazyLock::new(|| metrics::counter("shipments_created_total", "Shipments created."));
+
+/// Stock moved in or out of warehouses, by `direction`: `inbound` or `outbound`.
+pub static STOCK_MOVEMENTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let counter = metrics::counter_vec(
+        "stock_movements_total",
+        "Stock movements recorded, by direction.",
+        &["direction"],
+    );
+    for direction in ["inbound", "outbound"] {
+        counter.with_label_values(&[direction]);
+    }
+    counter
+});
+
+/// Exports the counters from zero, before anything is recorded.
+pub(crate) fn register() {
+    LazyLock::force(&SHIPMENTS_CREATED);
+    LazyLock::force(&STOCK_MOVEMENTS);
+}
@@ -1,6 +1,13 @@
 use lib_core::AppState;
+use lib_security::guard::{Create, Delete, GuardedRoutes, Read, Resource, Update};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
+pub struct Shipment;
+
+impl Resource for Shipment {
+    const NAME: &'static str = "shipment";
+}
+
 #[utoipa::path(post, tag = "Shipment Management", path = "/")]
 async fn create() {}
 
@@ -19,12 +26,12 @@ async fn update() {}
 #[utoipa::path(delete, tag = "Shipment Management", path = "/{id}")]
 async fn remove() {}
 
-pub fn routes() -> OpenApiRouter<AppState> {
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
-        .routes(routes!(create))
-        .routes(routes!(read))
-        .routes(routes!(search))
-        .routes(routes!(one))
-        .routes(routes!(update))
-        .routes(routes!(remove))
+        .guarded::<Shipment, Create>(state, routes!(create))
+        .guarded::<Shipment, Read>(state, routes!(read))
+        .guarded::<Shipment, Read>(state, routes!(search))
+        .guarded::<Shipment, Read>(state, routes!(one))
+        .guarded::<Shipment, Update>(state, routes!(update))
+        .guarded::<Shipment, Delete>(state, routes!(remove))
 }
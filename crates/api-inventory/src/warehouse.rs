@@ -1,6 +1,13 @@
 use lib_core::AppState;
+use lib_security::guard::{Create, Delete, GuardedRoutes, Read, Resource, Update};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
+pub struct Warehouse;
+
+impl Resource for Warehouse {
+    const NAME: &'static str = "warehouse";
+}
+
 #[utoipa::path(post, tag = "Warehouse Management", path = "/")]
 async fn create() {}
 
@@ -19,12 +26,12 @@ async fn update() {}
 #[utoipa::path(delete, tag = "Warehouse Management", path = "/{id}")]
 async fn remove() {}
 
-pub fn routes() -> OpenApiRouter<AppState> {
+pub fn routes(state: &AppState) -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
-        .routes(routes!(create))
-        .routes(routes!(read))
-        .routes(routes!(search))
-        .routes(routes!(one))
-        .routes(routes!(update))
-        .routes(routes!(remove))
+        .guarded::<Warehouse, Create>(state, routes!(create))
+        .guarded::<Warehouse, Read>(state, routes!(read))
+        .guarded::<Warehouse, Read>(state, routes!(search))
+        .guarded::<Warehouse, Read>(state, routes!(one))
+        .guarded::<Warehouse, Update>(state, routes!(update))
+        .guarded::<Warehouse, Delete>(state, routes!(remove))
 }
@@ -0,0 +1,37 @@
+use std::{path::Path, process::Command};
+
+fn main() {
+    // embedded by `sqlx::migrate!`, the readiness probe compares them to the applied ones
+    println!("cargo:rerun-if-changed=../../migrations");
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+
+    let git = Path::new("../../.git");
+    for file in ["HEAD", "packed-refs"] {
+        if git.join(file).exists() {
+            println!("cargo:rerun-if-changed=../../.git/{file}");
+        }
+    }
+    if let Ok(head) = std::fs::read_to_string(git.join("HEAD"))
+        && let Some(reference) = head.trim().strip_prefix("ref: ")
+        && git.join(reference).exists()
+    {
+        println!("cargo:rerun-if-changed=../../.git/{reference}");
+    }
+
+    // images are built without the repository, their build passes the sha instead
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".into());
+
+    println!("cargo:rustc-env=GIT_SHA={sha}");
+}
@@ -0,0 +1,183 @@
+use std::{io::ErrorKind, path::Path, time::Duration};
+
+use axum::{Json, extract::State, http::StatusCode};
+use lib_core::AppState;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use utoipa::ToSchema;
+
+/// Migrations the binary was built with, applied by `sqlx migrate run` before deploys.
+static MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
+
+/// Longest a single check may take, a hanging dependency counts as unavailable.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Status {
+    Ok,
+    Unavailable,
+}
+
+/// Outcome of probing one dependency.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Check {
+    #[schema(example = "database")]
+    pub name: &'static str,
+    pub status: Status,
+    /// Why the dependency is unavailable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "connection refused")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Health {
+    /// `unavailable` as soon as one check is.
+    pub status: Status,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<Check>,
+}
+
+async fn check(name: &'static str, probe: impl Future<Output = Result<(), String>>) -> Check {
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, probe)
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {}s", CHECK_TIMEOUT.as_secs())));
+
+    match outcome {
+        Ok(()) => Check {
+            name,
+            status: Status::Ok,
+            detail: None,
+        },
+        Err(detail) => Check {
+            name,
+            status: Status::Unavailable,
+            detail: Some(detail),
+        },
+    }
+}
+
+async fn database(db: &DatabaseConnection) -> Result<(), String> {
+    db.ping().await.map_err(|err| err.to_string())
+}
+
+/// Versions of the up migrations of `migrator` missing from `applied`.
+fn pending(migrator: &Migrator, applied: &[i64]) -> Vec<i64> {
+    migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect()
+}
+
+async fn migrations(db: &DatabaseConnection) -> Result<(), String> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            "SELECT version FROM _sqlx_migrations WHERE success",
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let applied = rows
+        .iter()
+        .map(|row| row.try_get::<i64>("", "version"))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+
+    match pending(&MIGRATOR, &applied).as_slice() {
+        [] => Ok(()),
+        pending => Err(format!(
+            "{} pending migration(s): {}",
+            pending.len(),
+            pending
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Writes and removes a file, catching missing directories, read-only mounts and full disks.
+async fn storage(directory: &Path) -> Result<(), String> {
+    let probe = directory.join(".ready");
+
+    tokio::fs::write(&probe, b"")
+        .await
+        .map_err(|err| format!("{} is not writable: {err}", directory.display()))?;
+
+    match tokio::fs::remove_file(&probe).await {
+        // removed by a concurrent probe
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(format!("{} is not writable: {err}", directory.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn shutdown(state: &AppState) -> Result<(), String> {
+    if state.shutdown.is_cancelled() {
+        Err("draining in-flight requests".into())
+    } else {
+        Ok(())
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Monitoring",
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Every dependency is available", body = Health),
+        (status = 503, description = "A dependency is unavailable or the server is shutting down", body = Health)
+    )
+)]
+pub(crate) async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Health>) {
+    let (shutdown, database, migrations, storage) = tokio::join!(
+        check("shutdown", shutdown(&state)),
+        check("database", database(&state.db)),
+        check("migrations", migrations(&state.db)),
+        check("storage", storage(&state.config.file.directory)),
+    );
+    let checks = vec![shutdown, database, migrations, storage];
+
+    if checks.iter().all(|check| check.status == Status::Ok) {
+        (
+            StatusCode::OK,
+            Json(Health {
+                status: Status::Ok,
+                checks,
+            }),
+        )
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(Health {
+                status: Status::Unavailable,
+                checks,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_migrations_not_applied_yet() {
+        let versions = MIGRATOR
+            .iter()
+            .filter(|migration| migration.migration_type.is_up_migration())
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+        let (latest, applied) = versions.split_last().unwrap();
+
+        assert_eq!(pending(&MIGRATOR, &versions), Vec::<i64>::new());
+        assert_eq!(pending(&MIGRATOR, applied), [*latest]);
+    }
+}
@@ -1,14 +1,154 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+use axum::{Extension, Json};
+use lib_core::AppState;
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use health::{Health, Status};
+
+mod health;
+mod metrics;
+
+/// Build the server is running.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+struct Version {
+    #[schema(example = "0.6.1")]
+    version: &'static str,
+    /// Commit the binary was built from, `unknown` when it could not be determined.
+    #[schema(example = "7ca0a94d2f5e0b8a1c3e6f9d4b2a7c8e5f1d3b9a")]
+    git_sha: &'static str,
+}
+
+#[utoipa::path(
+    get,
+    tag = "Monitoring",
+    path = "/health/live",
+    responses(
+        (status = 200, description = "The process is up and serving requests", body = Health)
+    )
+)]
+async fn live() -> Json<Health> {
+    Json(Health {
+        status: Status::Ok,
+        checks: Vec::new(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    tag = "Monitoring",
+    path = "/version",
+    responses(
+        (status = 200, description = "Version and commit of the running build", body = Version)
+    )
+)]
+async fn version(Extension(version): Extension<Version>) -> Json<Version> {
+    Json(version)
+}
+
+/// Probes and metrics served from the root of the server for the orchestrator,
+/// unauthenticated.
+///
+/// `server_version` is the one reported by `/version`, the version of the server binary
+/// rather than of this crate.
+pub fn routes(server_version: &'static str) -> OpenApiRouter<AppState> {
+    metrics::register();
+
+    OpenApiRouter::new()
+        .routes(routes!(live))
+        .routes(routes!(health::ready))
+        .routes(routes!(version))
+        .routes(routes!(metrics::metrics))
+        .layer(Extension(Version {
+            version: server_version,
+            git_sha: env!("GIT_SHA"),
+        }))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
+    use axum::{body::Body, extract::Request, http::StatusCode};
+    use lib_core::{config::Config, mailer::LogMailer};
+    use sea_orm::DatabaseConnection;
+    use tower::ServiceExt;
+
+    fn state() -> AppState {
+        let mut config = Config::default();
+        config.file.directory = std::env::temp_dir();
+
+        AppState {
+            config: Arc::new(config),
+            db: DatabaseConnection::Disconnected,
+            keys: Arc::default(),
+            revocations: Arc::default(),
+            password_params: argon2::Params::default(),
+            mailer: Arc::new(LogMailer),
+            oidc_discovery: Arc::default(),
+            shutdown: Default::default(),
+        }
+    }
+
+    async fn get(state: AppState, uri: &str) -> (StatusCode, String) {
+        let (router, _) = routes("1.2.3").split_for_parts();
+        let response = router
+            .with_state(state)
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn reports_liveness_without_checks() {
+        let (status, body) = get(state(), "/health/live").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"status":"ok"}"#);
+    }
+
+    #[tokio::test]
+    async fn reports_the_running_version() {
+        let (status, body) = get(state(), "/version").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with(r#"{"version":"1.2.3","git_sha":""#));
+    }
+
+    #[tokio::test]
+    async fn exposes_metrics_in_the_prometheus_format() {
+        let (status, body) = get(state(), "/metrics").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("# TYPE lms_db_pool_max_connections gauge"));
+    }
+
+    #[tokio::test]
+    async fn is_not_ready_without_a_database() {
+        let (status, body) = get(state(), "/health/ready").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains(r#"{"name":"database","status":"unavailable""#));
+        assert!(body.contains(r#"{"name":"storage","status":"ok"}"#));
+        assert!(body.contains(r#"{"name":"shutdown","status":"ok"}"#));
+    }
+
+    #[tokio::test]
+    async fn is_not_ready_while_draining() {
+        let state = state();
+        state.shutdown.cancel();
+
+        let (status, body) = get(state, "/health/ready").await;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains(r#"{"name":"shutdown","status":"unavailable""#));
     }
 }
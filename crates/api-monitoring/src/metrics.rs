@@ -0,0 +1,59 @@
+use std::sync::LazyLock;
+
+use axum::{extract::State, http::header::CONTENT_TYPE, response::IntoResponse};
+use lib_core::{AppState, metrics, result::Result};
+use prometheus::{IntGauge, IntGaugeVec};
+use sea_orm::DatabaseConnection;
+
+static DB_POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    metrics::gauge_vec(
+        "db_pool_connections",
+        "Open connections of the database pool, by state.",
+        &["state"],
+    )
+});
+
+static DB_POOL_MAX_CONNECTIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    metrics::gauge(
+        "db_pool_max_connections",
+        "Connections the database pool may open.",
+    )
+});
+
+/// Exports the gauges before the first scrape updates them.
+pub(crate) fn register() {
+    LazyLock::force(&DB_POOL_CONNECTIONS);
+    LazyLock::force(&DB_POOL_MAX_CONNECTIONS);
+}
+
+/// Samples the pool of `db`, gauges are only worth updating when scraped.
+fn record_pool(db: &DatabaseConnection) {
+    // the other variants have no pool, and the accessor panics on them
+    let DatabaseConnection::SqlxPostgresPoolConnection(_) = db else {
+        return;
+    };
+
+    let pool = db.get_postgres_connection_pool();
+    let open = i64::from(pool.size());
+    let idle = pool.num_idle() as i64;
+
+    DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(open - idle);
+    DB_POOL_MAX_CONNECTIONS.set(i64::from(pool.options().get_max_connections()));
+}
+
+#[utoipa::path(
+    get,
+    tag = "Monitoring",
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain")
+    )
+)]
+pub(crate) async fn metrics(State(state): State<AppState>) -> Result<impl IntoResponse> {
+    record_pool(&state.db);
+
+    Ok(([(CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics::render()?))
+}
@@ -1,5 +1,17 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+use lib_core::{error::Error, result::Result};
+use qrcode::{QrCode, render::svg};
+
+/// Smallest width and height of a rendered code in pixels, large enough for phone cameras.
+const MIN_DIMENSION: u32 = 200;
+
+/// Renders `data` as an SVG QR code.
+pub fn svg(data: &str) -> Result<String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| Error::Custom(err.into()))?;
+
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(MIN_DIMENSION, MIN_DIMENSION)
+        .build())
 }
 
 #[cfg(test)]
@@ -7,8 +19,11 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn renders_svg() {
+        let image =
+            svg("otpauth://totp/Logistics:johndoe@email.com?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(image.starts_with("<?xml"));
+        assert!(image.contains("<svg"));
     }
 }
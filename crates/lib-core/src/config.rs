@@ -0,0 +1,653 @@
+use std::{fmt, path::PathBuf};
+
+use axum::http::HeaderValue;
+use serde::{Deserialize, Serialize};
+
+use crate::oidc::OidcProvider;
+
+/// Prefix of environment variables overriding the configuration, followed by the section
+/// and the key, e.g. `RUST_JWT_EXPIRY` sets `jwt.expiry`.
+pub const ENV_PREFIX: &str = "RUST_";
+
+/// Environment variable naming the configuration file, `config.toml` when unset.
+pub const FILE_VAR: &str = "RUST_CONFIG_FILE";
+
+const DEFAULT_FILE: &str = "config.toml";
+
+/// `RUST_` variables read by the Rust runtime or the log filter rather than naming a
+/// setting.
+const RUNTIME_VARS: [&str; 6] = [
+    FILE_VAR,
+    "RUST_LOG",
+    "RUST_LOG_STYLE",
+    "RUST_BACKTRACE",
+    "RUST_LIB_BACKTRACE",
+    "RUST_MIN_STACK",
+];
+
+/// Sections environment variables may override.
+const SECTIONS: [&str; 8] = [
+    "server", "database", "jwt", "argon2", "mail", "file", "log", "oidc",
+];
+
+/// Settings of the server, layered from the built-in defaults, the configuration file and
+/// the environment, in increasing priority.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub jwt: JwtConfig,
+    pub argon2: Argon2Config,
+    pub mail: MailConfig,
+    pub file: FileConfig,
+    pub log: LogConfig,
+    /// External identity provider users may sign in with, disabled when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<OidcProvider>,
+    /// `RUST_` variables naming no section, warned about once logging is set up.
+    #[serde(skip)]
+    pub unknown_vars: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// Base url of the frontend, used to build links sent by email.
+    pub public_url: String,
+    /// Read client addresses from the rightmost `X-Forwarded-For` entry, only set behind a
+    /// reverse proxy overwriting or appending to it.
+    pub trust_proxy: bool,
+    /// Origins allowed to call the api from a browser, e.g. the frontend.
+    pub cors_origins: Vec<String>,
+    /// Seconds a request may take before it is answered with 503.
+    pub request_timeout: u64,
+    /// Largest accepted request body in bytes, uploads are limited by `file.max_upload_size`.
+    pub body_limit: usize,
+    /// Seconds in-flight requests are given to finish once SIGTERM or SIGINT is received,
+    /// remaining connections are closed afterwards.
+    pub shutdown_timeout: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0".into(),
+            port: 8000,
+            public_url: "http://localhost:3000".into(),
+            trust_proxy: false,
+            cors_origins: vec!["http://localhost:3000".into()],
+            request_timeout: 30,
+            body_limit: 1024 * 1024,
+            shutdown_timeout: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Required, there is no default database.
+    pub url: String,
+}
+
+/// Lifetimes are in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// Lifetime of an access token.
+    pub expiry: u64,
+    /// Lifetime of a refresh token.
+    pub refresh_expiry: u64,
+    /// Tolerated clock skew when validating token timestamps.
+    pub leeway: u64,
+    /// Age of the signing key at which a new one is generated.
+    pub key_rotation: u64,
+    /// Authorize from the `scope` claim of access tokens alone, without reading grants from
+    /// the database. Grant changes then apply when the token is refreshed.
+    pub scope_authorization: bool,
+    /// Encrypts the signing keys stored in the database. Keys are stored in plain text while
+    /// unset, keys stored before it was set keep loading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_passphrase: Option<String>,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            expiry: 3600,
+            refresh_expiry: 30 * 24 * 3600,
+            leeway: 60,
+            key_rotation: 7 * 24 * 3600,
+            scope_authorization: false,
+            key_passphrase: None,
+        }
+    }
+}
+
+/// Argon2id cost of new password hashes, existing ones are upgraded on login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Argon2Config {
+    /// In KiB.
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        Self {
+            memory_cost: 19456,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Argon2Config {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_cost, self.time_cost, self.parallelism, None)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// Write outgoing mail to files in this directory instead of logging it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Where uploaded files are stored.
+    pub directory: PathBuf,
+    /// Largest accepted upload in bytes.
+    pub max_upload_size: usize,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            directory: "uploads".into(),
+            max_upload_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Multi-line and colored, for reading in a terminal.
+    Pretty,
+    /// One object per line, for log collectors.
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `tracing_subscriber::EnvFilter` directives, `RUST_LOG` takes precedence when set.
+    pub filter: String,
+    pub format: LogFormat,
+    /// OTLP/HTTP traces endpoint spans are exported to, e.g.
+    /// `http://localhost:4318/v1/traces` for a local collector. Disabled when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of exported spans.
+    pub service_name: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info,sqlx=warn".into(),
+            format: LogFormat::Pretty,
+            otlp_endpoint: None,
+            service_name: "logistics-management-system".into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The configuration file, or the layers merged over it, is not valid TOML or does not
+    /// match [`Config`].
+    Parse(toml::de::Error),
+    /// An environment variable does not hold a value of the type of its key.
+    Env { var: String, expected: &'static str },
+    /// Settings that parsed but cannot work, every problem found is listed.
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "cannot read config file {}: {source}", path.display())
+            }
+            ConfigError::Parse(err) => write!(f, "invalid configuration: {err}"),
+            ConfigError::Env { var, expected } => {
+                write!(f, "environment variable {var} must be {expected}")
+            }
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads the configuration file named by [`FILE_VAR`], optional unless the variable is
+    /// set, and the environment overrides.
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, required) = match std::env::var(FILE_VAR) {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_FILE), false),
+        };
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(file) => Some(file),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => None,
+            Err(source) => return Err(ConfigError::Io { path, source }),
+        };
+
+        Self::from_sources(file.as_deref(), std::env::vars())
+    }
+
+    /// Layers the `file` contents and the `vars` overrides on the defaults.
+    pub fn from_sources(
+        file: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut table = toml::Table::try_from(Config::default())
+            .expect("the default configuration serializes to a table");
+
+        if let Some(file) = file {
+            merge(&mut table, file.parse().map_err(ConfigError::Parse)?);
+        }
+
+        let settings = settings();
+        let mut unknown_keys = Vec::new();
+        let mut unknown_vars = Vec::new();
+        for (var, value) in vars {
+            match override_from_env(&mut table, &settings, &var, &value)? {
+                Override::Applied => {}
+                Override::UnknownKey => {
+                    unknown_keys.push(format!("environment variable {var} names no setting"))
+                }
+                Override::UnknownSection => unknown_vars.push(var),
+            }
+        }
+        // a misspelled override would otherwise leave its default in place unnoticed
+        if !unknown_keys.is_empty() {
+            return Err(ConfigError::Invalid(unknown_keys));
+        }
+
+        // parsed from text so errors name the offending key
+        let mut config: Config = toml::from_str(&table.to_string()).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        config.unknown_vars = unknown_vars;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.database.url.is_empty() {
+            problems.push("database.url is required, set RUST_DATABASE_URL".to_string());
+        }
+        if self.server.port == 0 {
+            problems.push("server.port must not be 0".into());
+        }
+        for origin in &self.server.cors_origins {
+            if origin == "*" || HeaderValue::from_str(origin).is_err() {
+                problems.push(format!(
+                    "server.cors_origins has an invalid origin `{origin}`"
+                ));
+            }
+        }
+        if self.server.request_timeout == 0 {
+            problems.push("server.request_timeout must be positive".into());
+        }
+        if self.server.shutdown_timeout == 0 {
+            problems.push("server.shutdown_timeout must be positive".into());
+        }
+        if self.jwt.expiry == 0 {
+            problems.push("jwt.expiry must be positive".into());
+        }
+        if self.jwt.refresh_expiry <= self.jwt.expiry {
+            problems.push("jwt.refresh_expiry must be longer than jwt.expiry".into());
+        }
+        if self.jwt.key_rotation == 0 {
+            problems.push("jwt.key_rotation must be positive".into());
+        }
+        if self
+            .jwt
+            .key_passphrase
+            .as_ref()
+            .is_some_and(|passphrase| passphrase.is_empty())
+        {
+            problems.push("jwt.key_passphrase must not be empty, leave it unset instead".into());
+        }
+        if let Err(err) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            problems.push(format!("log.filter is invalid: {err}"));
+        }
+        if let Err(err) = self.argon2.params() {
+            problems.push(format!("argon2 costs are out of range: {err}"));
+        }
+        if let Some(oidc) = &self.oidc {
+            for (key, value) in [
+                ("issuer", &oidc.issuer),
+                ("client_id", &oidc.client_id),
+                ("redirect_uri", &oidc.redirect_uri),
+            ] {
+                if value.is_empty() {
+                    problems.push(format!("oidc.{key} must not be empty"));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+/// Merges `layer` into `base`, tables key by key and other values replacing the base.
+fn merge(base: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Every setting, the optional ones unset by default included.
+fn settings() -> toml::Table {
+    let mut config = Config::default();
+    config.jwt.key_passphrase = Some(String::new());
+    config.mail.directory = Some(PathBuf::new());
+    config.log.otlp_endpoint = Some(String::new());
+    config.oidc = Some(OidcProvider {
+        issuer: String::new(),
+        client_id: String::new(),
+        client_secret: Some(String::new()),
+        redirect_uri: String::new(),
+    });
+
+    toml::Table::try_from(config).expect("the configuration serializes to a table")
+}
+
+/// Outcome of [`override_from_env`] for one variable.
+enum Override {
+    /// The variable set a setting, or is not meant for the configuration.
+    Applied,
+    /// `RUST_<SECTION>_<KEY>` with a key the section does not have, likely misspelled.
+    UnknownKey,
+    /// A `RUST_` variable naming no section, possibly read by a tool or the runtime.
+    UnknownSection,
+}
+
+/// Applies `RUST_<SECTION>_<KEY>=value`, other variables are ignored.
+///
+/// The value is parsed as the type of the key in the layers below, comma separated for
+/// lists, keys without one take the value as a string.
+fn override_from_env(
+    table: &mut toml::Table,
+    settings: &toml::Table,
+    var: &str,
+    value: &str,
+) -> Result<Override, ConfigError> {
+    let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+        return Ok(Override::Applied);
+    };
+    if RUNTIME_VARS.contains(&var) {
+        return Ok(Override::Applied);
+    }
+    let name = name.to_lowercase();
+
+    let Some((section, key)) = SECTIONS.iter().find_map(|section| {
+        let key = name.strip_prefix(section)?.strip_prefix('_')?;
+        Some((*section, key))
+    }) else {
+        return Ok(Override::UnknownSection);
+    };
+    if !settings
+        .get(section)
+        .and_then(toml::Value::as_table)
+        .is_some_and(|settings| settings.contains_key(key))
+    {
+        return Ok(Override::UnknownKey);
+    }
+
+    let section = table
+        .entry(section)
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    let toml::Value::Table(section) = section else {
+        return Ok(Override::Applied);
+    };
+
+    let error = |expected| ConfigError::Env {
+        var: var.to_string(),
+        expected,
+    };
+    let value = match section.get(key) {
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(value.trim().parse().map_err(|_| error("an integer"))?)
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(value.trim().parse().map_err(|_| error("a number"))?)
+        }
+        Some(toml::Value::Array(_)) => toml::Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(value.trim().parse().map_err(|_| error("true or false"))?)
+        }
+        _ => toml::Value::String(value.to_string()),
+    };
+
+    section.insert(key.to_string(), value);
+
+    Ok(Override::Applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect()
+    }
+
+    const DATABASE: (&str, &str) = ("RUST_DATABASE_URL", "postgres://localhost/lms");
+
+    #[test]
+    fn layers_environment_over_file_over_defaults() {
+        let file = r#"
+            [server]
+            port = 9000
+            public_url = "https://lms.example.com"
+
+            [jwt]
+            expiry = 900
+        "#;
+
+        let config = Config::from_sources(
+            Some(file),
+            vars(&[
+                DATABASE,
+                ("RUST_SERVER_PORT", "9100"),
+                ("RUST_JWT_SCOPE_AUTHORIZATION", "true"),
+                ("RUST_MAIL_DIRECTORY", "/tmp/mail"),
+                (
+                    "RUST_SERVER_CORS_ORIGINS",
+                    "https://lms.example.com, http://localhost:3000",
+                ),
+                ("RUST_LOG", "debug"),
+                ("RUST_LOG_FORMAT", "json"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(config.server.public_url, "https://lms.example.com");
+        assert_eq!(config.server.address, "0.0.0.0");
+        assert_eq!(config.jwt.expiry, 900);
+        assert!(config.jwt.scope_authorization);
+        assert_eq!(
+            config.server.cors_origins,
+            ["https://lms.example.com", "http://localhost:3000"]
+        );
+        assert_eq!(config.mail.directory, Some("/tmp/mail".into()));
+        assert!(config.oidc.is_none());
+        assert_eq!(config.log.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn parses_the_example_file() {
+        let example = include_str!("../../../config.example.toml");
+
+        let config = Config::from_sources(Some(example), Vec::new()).unwrap();
+
+        assert_eq!(
+            config.file.max_upload_size,
+            FileConfig::default().max_upload_size
+        );
+    }
+
+    #[test]
+    fn enables_oidc_from_the_environment() {
+        let config = Config::from_sources(
+            None,
+            vars(&[
+                DATABASE,
+                ("RUST_OIDC_ISSUER", "https://accounts.example.com"),
+                ("RUST_OIDC_CLIENT_ID", "lms"),
+                ("RUST_OIDC_REDIRECT_URI", "http://localhost:3000/callback"),
+            ]),
+        )
+        .unwrap();
+
+        let oidc = config.oidc.unwrap();
+        assert_eq!(oidc.client_id, "lms");
+        assert_eq!(oidc.client_secret, None);
+
+        let err = Config::from_sources(
+            None,
+            vars(&[
+                DATABASE,
+                ("RUST_OIDC_ISSUER", "https://accounts.example.com"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("client_id"), "{err}");
+    }
+
+    #[test]
+    fn rejects_malformed_values_with_their_source() {
+        let err = Config::from_sources(None, vars(&[DATABASE, ("RUST_SERVER_PORT", "http")]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable RUST_SERVER_PORT must be an integer"
+        );
+
+        let err =
+            Config::from_sources(Some("[server]\nport = 70000"), vars(&[DATABASE])).unwrap_err();
+        assert!(err.to_string().contains("port"), "{err}");
+
+        let err =
+            Config::from_sources(Some("[jwt]\nexpiry_seconds = 1"), vars(&[DATABASE])).unwrap_err();
+        assert!(err.to_string().contains("expiry_seconds"), "{err}");
+    }
+
+    #[test]
+    fn rejects_variables_naming_no_setting_of_a_section() {
+        let err = Config::from_sources(
+            None,
+            vars(&[
+                DATABASE,
+                ("RUST_SERVER_PROT", "8000"),
+                ("RUST_JWT_SECRET", "secret"),
+                ("RUST_BACKTRACE", "1"),
+                ("RUST_JWT_KEY_PASSPHRASE", "passphrase"),
+            ]),
+        )
+        .unwrap_err();
+
+        let ConfigError::Invalid(problems) = err else {
+            panic!("{err}");
+        };
+        assert_eq!(
+            problems,
+            [
+                "environment variable RUST_SERVER_PROT names no setting",
+                "environment variable RUST_JWT_SECRET names no setting",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_other_variables_to_warn_about() {
+        let config = Config::from_sources(
+            None,
+            vars(&[
+                DATABASE,
+                ("RUST_VERSION", "1.85.0"),
+                ("RUST_TEST_THREADS", "1"),
+                ("RUST_BACKTRACE", "1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.unknown_vars, ["RUST_VERSION", "RUST_TEST_THREADS"]);
+    }
+
+    #[test]
+    fn lists_every_invalid_setting() {
+        let err = Config::from_sources(
+            None,
+            vars(&[
+                ("RUST_JWT_REFRESH_EXPIRY", "60"),
+                ("RUST_ARGON2_TIME_COST", "0"),
+            ]),
+        )
+        .unwrap_err();
+
+        let ConfigError::Invalid(problems) = err else {
+            panic!("{err}");
+        };
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(problems[0].starts_with("database.url"));
+    }
+}
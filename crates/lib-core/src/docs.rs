@@ -1,5 +1,157 @@
-use utoipa::OpenApi;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self, ContentBuilder, Ref, RefOr, ResponseBuilder,
+        path::Operation,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+use crate::error::{ErrorCode, ErrorResponse, FieldError, PROBLEM_JSON};
 
 #[derive(OpenApi, Debug)]
-#[openapi(info(description = "Logistics Management System"))]
+#[openapi(
+    info(description = "Logistics Management System"),
+    components(schemas(ErrorResponse, ErrorCode, FieldError)),
+    modifiers(&SecurityAddon)
+)]
 pub struct OpenAPI;
+
+impl OpenAPI {
+    /// Documents the problem details every operation of `openapi` may fail with.
+    ///
+    /// Run once the routes are merged, error responses declared with an [`ErrorResponse`]
+    /// body are served as [`PROBLEM_JSON`] and operations without a `default` response get
+    /// one.
+    pub fn document_problems(openapi: &mut openapi::OpenApi) {
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.options,
+                &mut item.head,
+                &mut item.patch,
+                &mut item.trace,
+            ];
+
+            for operation in operations.into_iter().flatten() {
+                document_operation(operation);
+            }
+        }
+    }
+}
+
+fn problem_schema() -> Ref {
+    Ref::from_schema_name("ErrorResponse")
+}
+
+fn document_operation(operation: &mut Operation) {
+    for (status, response) in operation.responses.responses.iter_mut() {
+        let RefOr::T(response) = response else {
+            continue;
+        };
+
+        if !(status.starts_with('4') || status.starts_with('5')) {
+            continue;
+        }
+
+        let is_problem = response
+            .content
+            .get("application/json")
+            .is_some_and(|content| content.schema == Some(RefOr::Ref(problem_schema())));
+
+        if is_problem {
+            let json = response.content.shift_remove("application/json");
+            response
+                .content
+                .extend(json.map(|json| (PROBLEM_JSON.to_string(), json)));
+        }
+    }
+
+    operation
+        .responses
+        .responses
+        .entry("default".into())
+        .or_insert_with(|| {
+            ResponseBuilder::new()
+                .description("Problem details of the failure")
+                .content(
+                    PROBLEM_JSON,
+                    ContentBuilder::new().schema(Some(problem_schema())).build(),
+                )
+                .build()
+                .into()
+        });
+}
+
+/// Registers the `bearer_auth` scheme referenced by guarded operations.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .build(),
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::Result;
+    use utoipa_axum::{router::OpenApiRouter, routes};
+
+    #[utoipa::path(
+        get,
+        path = "/shipments",
+        responses(
+            (status = 200, description = "Every shipment"),
+            (status = 404, description = "Unknown shipment", body = ErrorResponse)
+        )
+    )]
+    async fn shipments() -> Result<()> {
+        Ok(())
+    }
+
+    #[test]
+    fn documents_problem_details_on_every_operation() {
+        let (_, mut openapi) = OpenApiRouter::<()>::with_openapi(OpenAPI::openapi())
+            .routes(routes!(shipments))
+            .split_for_parts();
+
+        OpenAPI::document_problems(&mut openapi);
+
+        let responses = &openapi.paths.paths["/shipments"]
+            .get
+            .as_ref()
+            .unwrap()
+            .responses
+            .responses;
+        let content_types = |status: &str| match &responses[status] {
+            RefOr::T(response) => response.content.keys().cloned().collect::<Vec<_>>(),
+            RefOr::Ref(_) => unreachable!(),
+        };
+
+        assert_eq!(content_types("404"), [PROBLEM_JSON]);
+        assert_eq!(content_types("default"), [PROBLEM_JSON]);
+        assert!(content_types("200").is_empty());
+        assert!(
+            openapi
+                .components
+                .unwrap()
+                .schemas
+                .contains_key("ErrorResponse")
+        );
+    }
+}
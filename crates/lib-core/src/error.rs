@@ -1,4 +1,11 @@
-use axum::{Json, response::IntoResponse};
+use axum::{
+    Json,
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONTENT_TYPE, RETRY_AFTER},
+    },
+    response::IntoResponse,
+};
 use derive_more::From;
 use serde::{Deserialize, Serialize};
 use sqlx::types::uuid;
@@ -21,22 +28,249 @@ pub enum Error {
     AuthenticationError,
     // -- Authorization
     AuthorizationError,
+    // -- Throttling, seconds until the next attempt is allowed
+    #[from(skip)]
+    TooManyRequests(u64),
 }
 
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Media type of [`ErrorResponse`] bodies, see RFC 7807.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Stable identifier of an error, clients match on it instead of the status or the title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    ValidationFailed,
+    Unauthenticated,
+    Forbidden,
+    TooManyRequests,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::Unauthenticated => "unauthenticated",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::TooManyRequests => "too_many_requests",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// `type` of the problem document, a URI naming the code.
+    pub fn problem_type(&self) -> String {
+        format!("urn:lms:problem:{}", self.as_str())
+    }
+}
+
+/// Problem details document returned for every failed request, served as
+/// [`PROBLEM_JSON`].
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorResponse {
-    #[schema(example = 400)]
-    code: u16,
-    #[schema(example = "Bad Request")]
+    #[serde(rename = "type")]
+    #[schema(example = "urn:lms:problem:validation_failed")]
+    problem_type: String,
+    #[schema(example = "Unprocessable Entity")]
+    title: String,
+    #[schema(example = 422)]
+    status: u16,
+    code: ErrorCode,
+    #[schema(example = "The request body failed validation")]
+    detail: String,
+    /// Id of the failed request, also sent in the `x-request-id` header. Quote it when
+    /// reporting an internal error, its cause is only written to the server logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "3f0e5bb2-8d43-4f1c-9a43-cc2d2bd6a5e4")]
+    instance: Option<String>,
+    /// Failed validations, one entry per rule a field broke.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Path of the field in the request body.
+    #[schema(example = "password")]
+    field: String,
+    #[schema(example = "length is lower than 8")]
     message: String,
 }
 
+impl Error {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::RowNotFound => StatusCode::NOT_FOUND,
+            Error::Garde(_) | Error::Uuid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::AuthenticationError => StatusCode::UNAUTHORIZED,
+            Error::AuthorizationError => StatusCode::FORBIDDEN,
+            Error::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Custom(_) | Error::SeaOrm(_) | Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::RowNotFound => ErrorCode::NotFound,
+            Error::Garde(_) | Error::Uuid(_) => ErrorCode::ValidationFailed,
+            Error::AuthenticationError => ErrorCode::Unauthenticated,
+            Error::AuthorizationError => ErrorCode::Forbidden,
+            Error::TooManyRequests(_) => ErrorCode::TooManyRequests,
+            Error::Custom(_) | Error::SeaOrm(_) | Error::Io(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Explanation safe to return to the client.
+    fn detail(&self) -> String {
+        match self {
+            Error::RowNotFound => "The requested resource does not exist".into(),
+            Error::Garde(_) => "The request body failed validation".into(),
+            Error::Uuid(_) => "An identifier is not a valid UUID".into(),
+            Error::AuthenticationError => "Missing, invalid or expired credentials".into(),
+            Error::AuthorizationError => "The credentials lack a required permission".into(),
+            Error::TooManyRequests(seconds) => format!("Retry in {seconds} seconds"),
+            Error::Custom(_) | Error::SeaOrm(_) | Error::Io(_) => {
+                "The server failed to handle the request".into()
+            }
+        }
+    }
+}
+
+impl From<&Error> for ErrorResponse {
+    fn from(error: &Error) -> Self {
+        let status = error.status();
+        let code = error.code();
+
+        let errors = match error {
+            Error::Garde(report) => report
+                .iter()
+                .map(|(path, error)| FieldError {
+                    field: path.to_string(),
+                    message: error.message().to_string(),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut instance = crate::middleware::current_request_id();
+        if status.is_server_error() {
+            let request_id = instance.get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+            tracing::error!(%request_id, ?error, "internal error");
+        }
+
+        Self {
+            problem_type: code.problem_type(),
+            title: status.canonical_reason().unwrap_or_default().into(),
+            status: status.as_u16(),
+            code,
+            detail: error.detail(),
+            instance,
+            errors,
+        }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        Json(ErrorResponse {
-            code: 500,
-            message: "Internal server error".into(),
-        })
-        .into_response()
+        let mut response = (
+            self.status(),
+            [(CONTENT_TYPE, PROBLEM_JSON)],
+            Json(ErrorResponse::from(&self)),
+        )
+            .into_response();
+
+        if let Error::TooManyRequests(seconds) = self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_variants_to_statuses() {
+        for (error, status) in [
+            (Error::RowNotFound, StatusCode::NOT_FOUND),
+            (
+                Error::Garde(garde::Report::new()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (Error::AuthenticationError, StatusCode::UNAUTHORIZED),
+            (Error::AuthorizationError, StatusCode::FORBIDDEN),
+            (Error::TooManyRequests(3), StatusCode::TOO_MANY_REQUESTS),
+            (
+                Error::Custom("boom".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ] {
+            let response = error.into_response();
+
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        }
+    }
+
+    #[test]
+    fn renders_validation_failures_per_field() {
+        let mut report = garde::Report::new();
+        report.append(
+            garde::Path::new("email"),
+            garde::Error::new("not a valid email"),
+        );
+        report.append(garde::Path::new("password"), garde::Error::new("too short"));
+
+        let response = ErrorResponse::from(&Error::Garde(report));
+
+        assert_eq!(response.status, 422);
+        assert_eq!(response.code, ErrorCode::ValidationFailed);
+        assert_eq!(response.problem_type, "urn:lms:problem:validation_failed");
+        assert_eq!(response.instance, None);
+        assert_eq!(
+            response
+                .errors
+                .iter()
+                .map(|error| (error.field.as_str(), error.message.as_str()))
+                .collect::<Vec<_>>(),
+            [("email", "not a valid email"), ("password", "too short")]
+        );
+    }
+
+    #[test]
+    fn hides_internal_errors_behind_the_request_id() {
+        let response = ErrorResponse::from(&Error::Custom("connection refused".into()));
+
+        assert_eq!(response.status, 500);
+        assert_eq!(response.code, ErrorCode::Internal);
+        assert_eq!(response.title, "Internal Server Error");
+        assert!(!response.detail.contains("connection refused"));
+        assert!(response.errors.is_empty());
+        assert!(response.instance.is_some());
+    }
+
+    #[tokio::test]
+    async fn reports_the_current_request_id() {
+        let response = crate::middleware::REQUEST_ID
+            .scope("3f0e5bb2".into(), async {
+                ErrorResponse::from(&Error::RowNotFound)
+            })
+            .await;
+
+        assert_eq!(response.instance.as_deref(), Some("3f0e5bb2"));
     }
 }
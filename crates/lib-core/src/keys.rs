@@ -0,0 +1,82 @@
+use std::{
+    fmt::Debug,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use openssl::pkey::{PKey, Private, Public};
+
+/// An asymmetric key used to sign and verify access tokens, identified by the `kid` header.
+#[derive(Clone)]
+pub struct Key {
+    pub kid: String,
+    pub private_key: PKey<Private>,
+    pub public_key: PKey<Public>,
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Key").field("kid", &self.kid).finish()
+    }
+}
+
+/// In memory copy of the token keys that are currently valid.
+///
+/// The first key signs new tokens, every key is accepted for verification so tokens signed
+/// before a rotation stay valid until they expire. Persistence and rotation live in
+/// `lib_security::keys`.
+#[derive(Debug)]
+pub struct KeyRing {
+    keys: RwLock<(Vec<Key>, Instant)>,
+}
+
+impl Default for KeyRing {
+    fn default() -> Self {
+        Self {
+            keys: RwLock::new((Vec::new(), Instant::now())),
+        }
+    }
+}
+
+impl KeyRing {
+    /// Replaces the ring content, `keys` must be ordered from newest to oldest.
+    pub fn replace(&self, keys: Vec<Key>) {
+        *self.keys.write().unwrap_or_else(|err| err.into_inner()) = (keys, Instant::now());
+    }
+
+    pub fn signing_key(&self) -> Option<Key> {
+        self.keys
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .0
+            .first()
+            .cloned()
+    }
+
+    pub fn find(&self, kid: &str) -> Option<Key> {
+        self.keys
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .0
+            .iter()
+            .find(|key| key.kid == kid)
+            .cloned()
+    }
+
+    pub fn keys(&self) -> Vec<Key> {
+        self.keys
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .0
+            .clone()
+    }
+
+    /// Time elapsed since the ring was last replaced.
+    pub fn age(&self) -> Duration {
+        self.keys
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .1
+            .elapsed()
+    }
+}
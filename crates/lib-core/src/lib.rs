@@ -1,15 +1,38 @@
-use hmac::Hmac;
+use std::sync::Arc;
+
+use config::Config;
+use keys::KeyRing;
+use mailer::Mailer;
+use oidc::DiscoveryCache;
+use revocation::RevocationCache;
 use sea_orm::DatabaseConnection;
-use sha2::Sha256;
+use tokio_util::sync::CancellationToken;
 
+pub mod config;
 pub mod error;
 pub mod result;
+pub mod revocation;
 // pub mod test;
 pub mod docs;
+pub mod keys;
+pub mod mailer;
+pub mod metrics;
 pub mod middleware;
+pub mod oidc;
+pub mod shutdown;
+pub mod telemetry;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub db: DatabaseConnection,
-    pub key: Hmac<Sha256>,
+    pub keys: Arc<KeyRing>,
+    pub revocations: Arc<RevocationCache>,
+    /// Argon2id cost used for new password hashes, built from `config.argon2`.
+    pub password_params: argon2::Params,
+    pub mailer: Arc<dyn Mailer>,
+    pub oidc_discovery: Arc<DiscoveryCache>,
+    /// Cancelled once a shutdown signal is received, background tasks stop and readiness
+    /// reports unhealthy while in-flight requests drain.
+    pub shutdown: CancellationToken,
 }
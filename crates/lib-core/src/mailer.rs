@@ -0,0 +1,100 @@
+use std::{fmt::Debug, path::PathBuf};
+
+use chrono::Utc;
+use sea_orm::prelude::async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{error::Error, result::Result};
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Outgoing mail transport. Services depend on this instead of a concrete backend so tests
+/// and local setups can swap in [`LogMailer`] or [`FileMailer`].
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<()>;
+}
+
+/// Writes every message to the log instead of delivering it.
+#[derive(Debug, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<()> {
+        tracing::info!(to = %mail.to, subject = %mail.subject, body = %mail.body, "mail");
+        Ok(())
+    }
+}
+
+/// Stores every message as an `.eml` file inside `directory`.
+#[derive(Debug)]
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<()> {
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(Error::Io)?;
+
+        let path = self.directory.join(format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            Uuid::new_v4()
+        ));
+
+        tokio::fs::write(
+            path,
+            format!(
+                "To: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+                mail.to, mail.subject, mail.body
+            ),
+        )
+        .await
+        .map_err(Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_mailer_writes_message() {
+        let directory = std::env::temp_dir().join(format!("mailer-{}", Uuid::new_v4()));
+        let mailer = FileMailer::new(&directory);
+
+        mailer
+            .send(Mail {
+                to: "johndoe@email.com".into(),
+                subject: "Verify your email".into(),
+                body: "token".into(),
+            })
+            .await
+            .unwrap();
+
+        let mut entries = std::fs::read_dir(&directory).unwrap();
+        let content = std::fs::read_to_string(entries.next().unwrap().unwrap().path()).unwrap();
+
+        assert!(content.starts_with("To: johndoe@email.com\r\nSubject: Verify your email"));
+        assert!(entries.next().is_none());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::{sync::LazyLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder, core::Collector,
+};
+
+use crate::{error::Error, result::Result};
+
+/// Prefix of every metric name, e.g. `lms_http_request_duration_seconds`.
+pub const NAMESPACE: &str = "lms";
+
+/// Content type of [`render`], the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(|| {
+    Registry::new_custom(Some(NAMESPACE.into()), None).expect("namespace is a valid prefix")
+});
+
+static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram_vec(
+        "http_request_duration_seconds",
+        "Time taken to answer requests, by route and status.",
+        &["method", "route", "status"],
+        prometheus::DEFAULT_BUCKETS.to_vec(),
+    )
+});
+
+/// Adds `collector` to the registry rendered at `/metrics`.
+///
+/// Metrics are registered once, typically from a `LazyLock` static next to the code
+/// updating them. Panics when the name is taken or invalid, both being programming errors.
+pub fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    if let Err(err) = REGISTRY.register(Box::new(collector.clone())) {
+        panic!("failed to register metric: {err}");
+    }
+
+    collector
+}
+
+pub fn counter(name: &str, help: &str) -> IntCounter {
+    register(IntCounter::with_opts(Opts::new(name, help)).expect("valid counter options"))
+}
+
+pub fn counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    register(IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter options"))
+}
+
+pub fn gauge(name: &str, help: &str) -> IntGauge {
+    register(IntGauge::with_opts(Opts::new(name, help)).expect("valid gauge options"))
+}
+
+pub fn gauge_vec(name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    register(IntGaugeVec::new(Opts::new(name, help), labels).expect("valid gauge options"))
+}
+
+/// Histogram with the upper bounds of its `buckets`, in increasing order.
+pub fn histogram_vec(name: &str, help: &str, labels: &[&str], buckets: Vec<f64>) -> HistogramVec {
+    register(
+        HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets), labels)
+            .expect("valid histogram options"),
+    )
+}
+
+/// Every registered metric in the [`CONTENT_TYPE`] format.
+pub fn render() -> Result<String> {
+    TextEncoder::new()
+        .encode_to_string(&REGISTRY.gather())
+        .map_err(|err| Error::Custom(Box::new(err)))
+}
+
+/// Times every request into `http_request_duration_seconds`.
+///
+/// Requests matching no route share the `unmatched` route, so probing random paths cannot
+/// grow the number of series.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+
+    let response = next.run(request).await;
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method.as_str(), &route, response.status().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    #[test]
+    fn renders_registered_metrics_with_the_namespace() {
+        let counter = counter("tests_rendered_total", "Counted by the render test.");
+        counter.inc_by(3);
+
+        let rendered = render().unwrap();
+
+        assert!(rendered.contains("# TYPE lms_tests_rendered_total counter"));
+        assert!(rendered.contains("lms_tests_rendered_total 3"));
+    }
+
+    #[tokio::test]
+    async fn times_requests_by_route_and_status() {
+        let router = Router::new()
+            .route("/shipments/{id}", get(|| async { "shipment" }))
+            .layer(axum::middleware::from_fn(track_requests));
+
+        for uri in ["/shipments/1", "/shipments/2", "/unknown"] {
+            router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let rendered = render().unwrap();
+
+        assert!(rendered.contains(
+            r#"lms_http_request_duration_seconds_count{method="GET",route="/shipments/{id}",status="200"} 2"#
+        ));
+        assert!(rendered.contains(
+            r#"lms_http_request_duration_seconds_count{method="GET",route="unmatched",status="404"} 1"#
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+use std::{any::Any, convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{DefaultBodyLimit, MatchedPath, Request},
+    http::{
+        HeaderName, HeaderValue, Method, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, RETRY_AFTER, SET_COOKIE},
+    },
+    middleware::{Next, from_fn},
+    response::{IntoResponse, Response},
+    routing::Route,
+};
+use tower::{Layer, Service, ServiceBuilder};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    sensitive_headers::{SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
+use tracing::Span;
+
+use crate::{config::Config, error::Error, metrics};
+
+/// Header carrying the id of a request, taken from the client when it sent one.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    /// Id of the request handled by the current task, see [`request_id`].
+    pub static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, `None` outside of the [`request_id`] middleware.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Exposes the id set by [`SetRequestIdLayer`] to handlers through
+/// [`current_request_id`].
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    match id {
+        Some(id) => REQUEST_ID.scope(id, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Span every request is handled in, `user_id` is recorded once the caller is
+/// authenticated.
+fn request_span(request: &Request) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        route,
+        request_id,
+        user_id = tracing::field::Empty,
+    )
+}
+
+/// Answers a panicking handler like any other internal error.
+fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| {
+            panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+        })
+        .unwrap_or_else(|| "handler panicked".into());
+
+    Error::Custom(message.into()).into_response()
+}
+
+/// Origins of the frontend allowed to call the api from a browser.
+fn cors(config: &Config) -> CorsLayer {
+    let origins = config
+        .server
+        .cors_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok());
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, REQUEST_ID_HEADER])
+        .expose_headers([REQUEST_ID_HEADER, RETRY_AFTER])
+        .max_age(Duration::from_secs(3600))
+}
+
+/// Layers wrapped around every route, outermost first:
+///
+/// - credentials are marked sensitive so traces never print them,
+/// - requests get an id, reused from `x-request-id` when the client sent one, echoed in
+///   the response and reported by error responses,
+/// - requests are traced in a span naming their route and id, see [`request_span`],
+/// - requests are timed by route and status, see [`metrics::track_requests`],
+/// - panics become internal error responses,
+/// - CORS for the configured origins,
+/// - gzip compression of responses,
+/// - requests running longer than `server.request_timeout` are answered with 503, 408
+///   would blame the client for a slow handler.
+///
+/// Body size limits differ between route groups, see [`body_limit`].
+pub fn stack(
+    config: &Config,
+) -> impl Layer<
+    Route,
+    Service: Service<
+        Request,
+        Response: IntoResponse + 'static,
+        Error = Infallible,
+        Future: Send + 'static,
+    > + Clone
+                 + Send
+                 + Sync
+                 + 'static,
+> + Clone
++ Send
++ Sync
++ 'static {
+    ServiceBuilder::new()
+        .layer(SetSensitiveRequestHeadersLayer::new([
+            AUTHORIZATION,
+            COOKIE,
+        ]))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(from_fn(request_id))
+        .layer(from_fn(metrics::track_requests))
+        .layer(CatchPanicLayer::custom(panic_response))
+        .layer(cors(config))
+        .layer(CompressionLayer::new())
+        .layer(TimeoutLayer::with_status_code(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Duration::from_secs(config.server.request_timeout),
+        ))
+        .layer(SetSensitiveResponseHeadersLayer::new([SET_COOKIE]))
+}
+
+/// Rejects request bodies larger than `limit` bytes with 413, applied to a route group.
+pub fn body_limit(limit: usize) -> (DefaultBodyLimit, RequestBodyLimitLayer) {
+    // extractors have their own lower default, the group limit replaces it
+    (
+        DefaultBodyLimit::max(limit),
+        RequestBodyLimitLayer::new(limit),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::post};
+    use tower::ServiceExt;
+
+    async fn failing() -> Result<(), Error> {
+        Err(Error::Custom("database is down".into()))
+    }
+
+    async fn echo(body: String) -> String {
+        body
+    }
+
+    async fn panicking() -> &'static str {
+        panic!("handler bug")
+    }
+
+    fn router() -> Router {
+        let config = Config {
+            server: crate::config::ServerConfig {
+                cors_origins: vec!["http://localhost:3000".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Router::new()
+            .route("/failing", post(failing))
+            .route("/panicking", post(panicking))
+            .route("/echo", post(echo).layer(body_limit(8)))
+            .layer(stack(&config))
+    }
+
+    #[tokio::test]
+    async fn reports_the_request_id_in_error_responses() {
+        let response = router()
+            .oneshot(
+                Request::post("/failing")
+                    .header(&REQUEST_ID_HEADER, "3f0e5bb2")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "3f0e5bb2");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(r#""instance":"3f0e5bb2""#));
+    }
+
+    #[tokio::test]
+    async fn generates_missing_request_ids() {
+        let response = router()
+            .oneshot(Request::post("/echo").body(Body::from("ok")).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(&REQUEST_ID_HEADER));
+    }
+
+    #[tokio::test]
+    async fn answers_panics_with_an_internal_error() {
+        let response = router()
+            .oneshot(Request::post("/panicking").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[CONTENT_TYPE], crate::error::PROBLEM_JSON);
+    }
+
+    #[tokio::test]
+    async fn limits_request_bodies_per_route() {
+        let response = router()
+            .oneshot(
+                Request::post("/echo")
+                    .body(Body::from("longer than eight bytes"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn allows_configured_origins_only() {
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/echo")
+                .header("origin", origin)
+                .header("access-control-request-method", "POST")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let allowed = router()
+            .oneshot(preflight("http://localhost:3000"))
+            .await
+            .unwrap();
+        let denied = router()
+            .oneshot(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            allowed.headers()["access-control-allow-origin"],
+            "http://localhost:3000"
+        );
+        assert!(!denied.headers().contains_key("access-control-allow-origin"));
+    }
+
+    #[tokio::test]
+    async fn answers_slow_requests_as_unavailable() {
+        let config = Config {
+            server: crate::config::ServerConfig {
+                request_timeout: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route(
+                "/slow",
+                post(|| tokio::time::sleep(Duration::from_secs(30))),
+            )
+            .layer(stack(&config));
+
+        let response = router
+            .oneshot(Request::post("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Registration of this service as a client of an OpenID Connect provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OidcProvider {
+    /// Issuer identifier, the discovery document is read from
+    /// `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    /// Only confidential clients have one, public clients rely on PKCE alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    /// Callback url registered at the provider, served by `/api/v1/auth/oidc/callback`.
+    pub redirect_uri: String,
+}
+
+/// Endpoints of the provider read from its discovery document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Keeps the discovery document of the provider between logins instead of fetching it on
+/// every redirect and callback.
+///
+/// Entries expire after the `ttl` given on insert so changes at the provider are picked up.
+#[derive(Debug, Default)]
+pub struct DiscoveryCache {
+    entry: RwLock<Option<(Metadata, Instant)>>,
+}
+
+impl DiscoveryCache {
+    /// Cached document, `None` when not fetched yet or expired.
+    pub fn get(&self) -> Option<Metadata> {
+        let entry = self.entry.read().unwrap_or_else(|err| err.into_inner());
+
+        entry
+            .as_ref()
+            .filter(|(_, until)| *until > Instant::now())
+            .map(|(metadata, _)| metadata.clone())
+    }
+
+    pub fn insert(&self, metadata: Metadata, ttl: Duration) {
+        let mut entry = self.entry.write().unwrap_or_else(|err| err.into_inner());
+
+        *entry = Some((metadata, Instant::now() + ttl));
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+/// Entries above this count trigger a sweep of expired ones on insert.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Caches the revocation state of token ids (`jti`) and sessions (`sid`) so the token
+/// extractor does not hit the database on every request.
+///
+/// Revocations made by this process are inserted directly, revocations made by other
+/// replicas become visible once the cached "not revoked" entry expires.
+#[derive(Debug, Default)]
+pub struct RevocationCache {
+    entries: RwLock<HashMap<Uuid, (bool, Instant)>>,
+}
+
+impl RevocationCache {
+    /// Cached revocation state of `id`, `None` when unknown or expired.
+    pub fn get(&self, id: &Uuid) -> Option<bool> {
+        let entries = self.entries.read().unwrap_or_else(|err| err.into_inner());
+
+        entries
+            .get(id)
+            .filter(|(_, until)| *until > Instant::now())
+            .map(|(revoked, _)| *revoked)
+    }
+
+    pub fn insert(&self, id: Uuid, revoked: bool, ttl: Duration) {
+        let mut entries = self.entries.write().unwrap_or_else(|err| err.into_inner());
+
+        if entries.len() >= PRUNE_THRESHOLD {
+            let now = Instant::now();
+            entries.retain(|_, (_, until)| *until > now);
+        }
+
+        entries.insert(id, (revoked, Instant::now() + ttl));
+    }
+}
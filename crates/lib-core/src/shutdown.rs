@@ -0,0 +1,111 @@
+use std::{future::IntoFuture, io, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+/// Resolves once the process receives SIGINT or, on unix, SIGTERM.
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!(?err, "failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::error!(?err, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Cancels `token` on the first shutdown [`signal`], meant to be spawned at startup.
+pub async fn cancel_on_signal(token: CancellationToken) {
+    tokio::select! {
+        _ = signal() => {
+            tracing::info!("shutdown signal received, draining in-flight requests");
+            token.cancel();
+        }
+        // cancelled from elsewhere, e.g. a failing background task
+        _ = token.cancelled() => {}
+    }
+}
+
+/// Runs `server` until it stops by itself, or for at most `timeout` once `token` is
+/// cancelled.
+///
+/// `server` is expected to shut down gracefully on the same token, the timeout bounds how
+/// long slow requests and idle connections may hold up the exit.
+pub async fn drain<S>(server: S, token: &CancellationToken, timeout: Duration) -> io::Result<()>
+where
+    S: IntoFuture<Output = io::Result<()>>,
+{
+    let server = server.into_future();
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = token.cancelled() => {}
+    }
+
+    match tokio::time::timeout(timeout, server).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!(
+                timeout = timeout.as_secs(),
+                "drain timeout elapsed, closing remaining connections"
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_the_server_to_finish_draining() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let server = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Err(io::Error::other("closed"))
+        };
+
+        let result = drain(server, &token, Duration::from_secs(5)).await;
+
+        assert_eq!(result.unwrap_err().to_string(), "closed");
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_draining_after_the_timeout() {
+        let token = CancellationToken::new();
+        let server = std::future::pending::<io::Result<()>>();
+
+        tokio::spawn({
+            let token = token.clone();
+            async move { token.cancel() }
+        });
+
+        let result = drain(server, &token, Duration::from_millis(10)).await;
+
+        assert!(result.is_ok());
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,80 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::{
+    config::{LogConfig, LogFormat},
+    error::Error,
+    result::Result,
+};
+
+/// Installed logging, [`Telemetry::shutdown`] flushes the spans not exported yet.
+#[derive(Debug)]
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("failed to export the remaining spans: {err}");
+        }
+    }
+}
+
+/// Installs the global subscriber: events matching the filter are written to stdout in
+/// the configured format and, when an OTLP endpoint is set, spans are exported to it.
+pub fn init(config: &LogConfig) -> Result<Telemetry> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.filter).map_err(|err| Error::Custom(err.into()))?,
+    };
+
+    let output = match config.format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().pretty().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    let provider = config
+        .otlp_endpoint
+        .as_ref()
+        .map(|endpoint| {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+                .map_err(|err| Error::Custom(err.into()))?;
+
+            Ok::<_, Error>(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(
+                        Resource::builder()
+                            .with_service_name(config.service_name.clone())
+                            .build(),
+                    )
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let export = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(config.service_name.clone()))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(export)
+        .try_init()
+        .map_err(|err| Error::Custom(err.into()))?;
+
+    Ok(Telemetry { provider })
+}
@@ -1,21 +1,128 @@
-use sea_orm::{ActiveModelBehavior, Set, prelude::async_trait::async_trait};
+use sea_orm::{
+    ActiveEnum, ActiveModelBehavior, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait,
+    DbErr, EntityTrait, Iterable, QueryFilter, Set, TryInsertResult,
+    prelude::{Uuid, async_trait::async_trait},
+    sea_query::OnConflict,
+};
 
-use crate::{employee, users};
+use crate::{
+    employee, permission_audit, role,
+    sea_orm_active_enums::{AuthType, EmployeeRole, PermissionEvent},
+    user_role, users,
+};
+
+/// Stored in `users._password` for accounts that cannot log in with a password, such as
+/// generated employee accounts that have not gone through a password reset yet.
+pub const UNUSABLE_PASSWORD: &str = "!";
 
 #[async_trait]
 impl ActiveModelBehavior for employee::ActiveModel {
-    async fn before_save<C>(self, db: &C, insert: bool) -> Result<Self, sea_orm::DbErr>
+    async fn before_save<C>(mut self, db: &C, insert: bool) -> Result<Self, DbErr>
     where
         C: sea_orm::ConnectionTrait,
     {
-        if !insert {
+        if !insert || !self.auth_user_id.is_not_set() {
             return Ok(self);
         }
 
-        let mut user = users::ActiveModel::new();
+        // employees without an account get one bound to their work email, the password is
+        // set by the employee through the password reset flow.
+        let email = match &self.email {
+            ActiveValue::Set(Some(email)) | ActiveValue::Unchanged(Some(email)) => email.clone(),
+            _ => {
+                return Err(DbErr::Custom(
+                    "employee email is required to create an account".into(),
+                ));
+            }
+        };
+
+        let user = users::ActiveModel {
+            auth_type: Set(AuthType::BasicAuth),
+            email: Set(email),
+            password: Set(UNUSABLE_PASSWORD.into()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
 
-        // TODO: create auto generated password and email if not exists
+        self.auth_user_id = Set(user.id);
 
         Ok(self)
     }
+
+    /// Keeps the account assigned to the built in role named after the employee role, roles
+    /// assigned by hand are left alone.
+    async fn after_save<C>(
+        model: employee::Model,
+        db: &C,
+        _insert: bool,
+    ) -> Result<employee::Model, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let (current, previous): (Vec<_>, Vec<_>) = role::Entity::find()
+            .filter(role::Column::Name.is_in(EmployeeRole::iter().map(|role| role.to_value())))
+            .all(db)
+            .await?
+            .into_iter()
+            .partition(|role| role.name == model.role.to_value());
+
+        for role in previous {
+            let deleted = user_role::Entity::delete_many()
+                .filter(user_role::Column::UserId.eq(model.auth_user_id))
+                .filter(user_role::Column::RoleId.eq(role.id))
+                .exec(db)
+                .await?;
+
+            if deleted.rows_affected > 0 {
+                audit(db, PermissionEvent::Unassign, model.auth_user_id, role.id).await?;
+            }
+        }
+
+        if let Some(role) = current.first() {
+            let inserted = user_role::Entity::insert(user_role::ActiveModel {
+                user_id: Set(model.auth_user_id),
+                role_id: Set(role.id),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([user_role::Column::UserId, user_role::Column::RoleId])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(db)
+            .await?;
+
+            if let TryInsertResult::Inserted(_) = inserted {
+                audit(db, PermissionEvent::Assign, model.auth_user_id, role.id).await?;
+            }
+        }
+
+        Ok(model)
+    }
+}
+
+/// Records a role change made by the sync above in the permission audit trail, without an
+/// actor since whoever saved the employee is not known here.
+async fn audit<C>(db: &C, event: PermissionEvent, user_id: Uuid, role_id: Uuid) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    permission_audit::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        event: Set(event),
+        actor_id: Set(None),
+        user_id: Set(Some(user_id)),
+        role_id: Set(Some(role_id)),
+        entity_name: Set(None),
+        action: Set(None),
+        resource_column: Set(None),
+        resource_value: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
 }
@@ -2,12 +2,30 @@
 
 pub mod prelude;
 
+pub mod api_key;
+pub mod auth_event;
 pub mod department;
+pub mod email_verification;
 pub mod emergency_information;
 pub mod employee;
 pub mod extensions;
 pub mod file;
 pub mod job_information;
+pub mod login_throttle;
+pub mod mfa_challenge;
+pub mod oidc_login;
+pub mod password_reset;
+pub mod permission_audit;
 pub mod permissions;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod role;
+pub mod role_permission;
 pub mod sea_orm_active_enums;
+pub mod session;
+pub mod signing_key;
+pub mod user_identity;
+pub mod user_role;
+pub mod user_totp;
 pub mod users;
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.6
 
+use super::sea_orm_active_enums::PermissionAction;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -11,8 +12,12 @@ pub struct Model {
     pub user_id: Uuid,
     #[sea_orm(column_type = "Text")]
     pub entity_name: String,
-    #[sea_orm(column_type = "Text")]
-    pub action: String,
+    pub action: PermissionAction,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub resource_column: Option<String>,
+    pub resource_value: Option<Uuid>,
+    pub granted_by: Option<Uuid>,
+    pub granted_at: DateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,9 +1,27 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.6
 
+pub use super::api_key::Entity as ApiKey;
+pub use super::auth_event::Entity as AuthEvent;
 pub use super::department::Entity as Department;
+pub use super::email_verification::Entity as EmailVerification;
 pub use super::emergency_information::Entity as EmergencyInformation;
 pub use super::employee::Entity as Employee;
 pub use super::file::Entity as File;
 pub use super::job_information::Entity as JobInformation;
+pub use super::login_throttle::Entity as LoginThrottle;
+pub use super::mfa_challenge::Entity as MfaChallenge;
+pub use super::oidc_login::Entity as OidcLogin;
+pub use super::password_reset::Entity as PasswordReset;
+pub use super::permission_audit::Entity as PermissionAudit;
 pub use super::permissions::Entity as Permissions;
+pub use super::recovery_code::Entity as RecoveryCode;
+pub use super::refresh_token::Entity as RefreshToken;
+pub use super::revoked_token::Entity as RevokedToken;
+pub use super::role::Entity as Role;
+pub use super::role_permission::Entity as RolePermission;
+pub use super::session::Entity as Session;
+pub use super::signing_key::Entity as SigningKey;
+pub use super::user_identity::Entity as UserIdentity;
+pub use super::user_role::Entity as UserRole;
+pub use super::user_totp::Entity as UserTotp;
 pub use super::users::Entity as Users;
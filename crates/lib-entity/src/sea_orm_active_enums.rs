@@ -3,11 +3,28 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "auth_event_type")]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventType {
+    #[sea_orm(string_value = "login_success")]
+    LoginSuccess,
+    #[sea_orm(string_value = "login_failure")]
+    LoginFailure,
+    #[sea_orm(string_value = "throttled")]
+    Throttled,
+    #[sea_orm(string_value = "lockout")]
+    Lockout,
+    #[sea_orm(string_value = "unlock")]
+    Unlock,
+}
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "auth_type")]
 pub enum AuthType {
     #[sea_orm(string_value = "basic_auth")]
     BasicAuth,
+    #[sea_orm(string_value = "oidc")]
+    Oidc,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(
@@ -39,3 +56,29 @@ pub enum EmployeeStatus {
     #[sea_orm(string_value = "Inactive")]
     Inactive,
 }
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "permission_action")]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionAction {
+    #[sea_orm(string_value = "create")]
+    Create,
+    #[sea_orm(string_value = "read")]
+    Read,
+    #[sea_orm(string_value = "update")]
+    Update,
+    #[sea_orm(string_value = "delete")]
+    Delete,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "permission_event")]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionEvent {
+    #[sea_orm(string_value = "grant")]
+    Grant,
+    #[sea_orm(string_value = "revoke")]
+    Revoke,
+    #[sea_orm(string_value = "assign")]
+    Assign,
+    #[sea_orm(string_value = "unassign")]
+    Unassign,
+}
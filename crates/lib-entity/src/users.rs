@@ -17,16 +17,51 @@ pub struct Model {
     pub password: String,
     pub create_at: DateTime,
     pub updated_at: DateTime,
+    pub email_verified_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_key::Entity")]
+    ApiKey,
+    #[sea_orm(has_many = "super::email_verification::Entity")]
+    EmailVerification,
     #[sea_orm(has_many = "super::employee::Entity")]
     Employee,
     #[sea_orm(has_many = "super::file::Entity")]
     File,
+    #[sea_orm(has_many = "super::mfa_challenge::Entity")]
+    MfaChallenge,
+    #[sea_orm(has_many = "super::password_reset::Entity")]
+    PasswordReset,
     #[sea_orm(has_many = "super::permissions::Entity")]
     Permissions,
+    #[sea_orm(has_many = "super::recovery_code::Entity")]
+    RecoveryCode,
+    #[sea_orm(has_many = "super::refresh_token::Entity")]
+    RefreshToken,
+    #[sea_orm(has_many = "super::revoked_token::Entity")]
+    RevokedToken,
+    #[sea_orm(has_many = "super::session::Entity")]
+    Session,
+    #[sea_orm(has_many = "super::user_identity::Entity")]
+    UserIdentity,
+    #[sea_orm(has_many = "super::user_role::Entity")]
+    UserRole,
+    #[sea_orm(has_one = "super::user_totp::Entity")]
+    UserTotp,
+}
+
+impl Related<super::api_key::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiKey.def()
+    }
+}
+
+impl Related<super::email_verification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::EmailVerification.def()
+    }
 }
 
 impl Related<super::employee::Entity> for Entity {
@@ -41,10 +76,73 @@ impl Related<super::file::Entity> for Entity {
     }
 }
 
+impl Related<super::mfa_challenge::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaChallenge.def()
+    }
+}
+
+impl Related<super::password_reset::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordReset.def()
+    }
+}
+
 impl Related<super::permissions::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Permissions.def()
     }
 }
 
+impl Related<super::recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCode.def()
+    }
+}
+
+impl Related<super::refresh_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RefreshToken.def()
+    }
+}
+
+impl Related<super::revoked_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RevokedToken.def()
+    }
+}
+
+impl Related<super::session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
+
+impl Related<super::user_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentity.def()
+    }
+}
+
+impl Related<super::user_role::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserRole.def()
+    }
+}
+
+impl Related<super::user_totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserTotp.def()
+    }
+}
+
+impl Related<super::role::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_role::Relation::Role.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_role::Relation::Users.def().rev())
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
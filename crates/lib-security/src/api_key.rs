@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use lib_core::{AppState, error::Error, result::Result};
+use lib_entity::{api_key, prelude::ApiKey};
+use sea_orm::{ColumnTrait, Condition, EntityTrait, QueryFilter, sea_query::Expr};
+
+use crate::{JWTClaim, scope, secret};
+
+/// Marks bearer tokens that are API keys rather than signed access tokens.
+pub const PREFIX: &str = "lms_";
+
+/// Length of the part of a key stored in clear so owners can tell their keys apart.
+const DISPLAY_LENGTH: usize = PREFIX.len() + 8;
+
+/// `last_used_at` is only written when older than this, so a busy integration does not
+/// turn every request into a write.
+const LAST_USED_RESOLUTION: TimeDelta = TimeDelta::minutes(1);
+
+/// Generates a new API key, returns the key handed to the client once and the prefix
+/// stored with its hash.
+pub fn generate() -> (String, String) {
+    let key = format!("{PREFIX}{}", secret::generate_secret());
+    let prefix = key[..DISPLAY_LENGTH].to_string();
+
+    (key, prefix)
+}
+
+/// Whether the bearer token is an API key.
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(PREFIX)
+}
+
+/// Authenticates a request made with an API key, recording its use.
+///
+/// The claims carry the scopes of the key still held by its owner, so revoking a permission
+/// also narrows every key of the user.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<JWTClaim> {
+    let now = Utc::now().naive_utc();
+
+    let key = ApiKey::find()
+        .filter(api_key::Column::TokenHash.eq(secret::hash_secret(token)))
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or(Error::AuthenticationError)?;
+
+    if key.revoked_at.is_some() || key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(Error::AuthenticationError);
+    }
+
+    ApiKey::update_many()
+        .col_expr(api_key::Column::LastUsedAt, Expr::value(now))
+        .filter(api_key::Column::Id.eq(key.id))
+        .filter(
+            Condition::any()
+                .add(api_key::Column::LastUsedAt.is_null())
+                .add(api_key::Column::LastUsedAt.lt(now - LAST_USED_RESOLUTION)),
+        )
+        .exec(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let scopes = scope::token_scopes(
+        &state.db,
+        key.user_id,
+        Some(&key.scope),
+        state.config.jwt.scope_authorization,
+    )
+    .await?;
+
+    let ttl = key
+        .expires_at
+        .map(|expires_at| (expires_at - now).to_std().unwrap_or_default())
+        .unwrap_or(Duration::from_secs(state.config.jwt.expiry));
+
+    let mut claims = JWTClaim::issue(key.user_id, scopes, ttl);
+    claims.api_key_id = Some(key.id);
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_prefixed_keys() {
+        let (key, prefix) = generate();
+
+        assert!(is_api_key(&key));
+        assert!(key.starts_with(&prefix));
+        assert_eq!(prefix.len(), DISPLAY_LENGTH);
+        assert_ne!(generate().0, key);
+
+        // signed access tokens never start with the prefix
+        assert!(!is_api_key("eyJhbGciOiJSUzI1NiJ9.e30.c2ln"));
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::Utc;
+use lib_core::{error::Error, result::Result};
+use lib_entity::{
+    permission_audit, prelude::PermissionAudit, sea_orm_active_enums::PermissionEvent,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set, prelude::Uuid,
+};
+
+use crate::{JWTClaim, Permission, Scope};
+
+/// Subject of a permission change.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Target {
+    User(Uuid),
+    Role(Uuid),
+    /// Assignment of a role to a user.
+    UserRole {
+        user_id: Uuid,
+        role_id: Uuid,
+    },
+}
+
+/// Appends a permission change made by `actor` to the audit trail.
+pub(crate) async fn record<C>(
+    db: &C,
+    actor: &JWTClaim,
+    event: PermissionEvent,
+    target: Target,
+    grant: Option<(&str, Permission, &Scope)>,
+) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    let (user_id, role_id) = match target {
+        Target::User(id) => (Some(id), None),
+        Target::Role(id) => (None, Some(id)),
+        Target::UserRole { user_id, role_id } => (Some(user_id), Some(role_id)),
+    };
+    let (entity_name, action, (resource_column, resource_value)) = match grant {
+        Some((table, action, scope)) => (Some(table.to_string()), Some(action), scope.to_columns()),
+        None => (None, None, (None, None)),
+    };
+
+    permission_audit::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        event: Set(event),
+        actor_id: Set(Some(actor.subject)),
+        user_id: Set(user_id),
+        role_id: Set(role_id),
+        entity_name: Set(entity_name),
+        action: Set(action),
+        resource_column: Set(resource_column),
+        resource_value: Set(resource_value),
+        created_at: Set(Utc::now().naive_utc()),
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok(())
+}
+
+/// Most recent permission changes first, optionally only those concerning a user or a role.
+pub async fn history(
+    db: &DatabaseConnection,
+    user_id: Option<Uuid>,
+    role_id: Option<Uuid>,
+    limit: u64,
+) -> Result<Vec<permission_audit::Model>> {
+    PermissionAudit::find()
+        .filter(
+            Condition::all()
+                .add_option(user_id.map(|id| permission_audit::Column::UserId.eq(id)))
+                .add_option(role_id.map(|id| permission_audit::Column::RoleId.eq(id))),
+        )
+        .order_by_desc(permission_audit::Column::CreatedAt)
+        .limit(limit)
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)
+}
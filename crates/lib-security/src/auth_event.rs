@@ -0,0 +1,43 @@
+use chrono::Utc;
+use lib_core::{error::Error, result::Result};
+use lib_entity::{auth_event, sea_orm_active_enums::AuthEventType};
+use sea_orm::{ActiveModelTrait, ConnectionTrait, Set, prelude::Uuid};
+
+use crate::client::ClientInfo;
+
+/// Account a login event concerns.
+#[derive(Debug, Clone, Copy)]
+pub struct Subject<'a> {
+    /// Unknown when no account matches the email.
+    pub user_id: Option<Uuid>,
+    pub email: Option<&'a str>,
+}
+
+/// Appends an authentication event to the log, `actor_id` is the administrator acting on
+/// the account, if any.
+pub async fn record<C>(
+    db: &C,
+    event: AuthEventType,
+    subject: Subject<'_>,
+    client: &ClientInfo,
+    actor_id: Option<Uuid>,
+) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    auth_event::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        event: Set(event),
+        user_id: Set(subject.user_id),
+        email: Set(subject.email.map(str::to_string)),
+        ip: Set(client.ip.clone()),
+        user_agent: Set(client.user_agent.clone()),
+        actor_id: Set(actor_id),
+        created_at: Set(Utc::now().naive_utc()),
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    Ok(())
+}
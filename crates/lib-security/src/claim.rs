@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::Utc;
+use jwt::{
+    AlgorithmType, Header, PKeyWithDigest, SignWithKey, Token, VerifyWithKey, header::HeaderType,
+};
+use lib_core::{error::Error, keys::KeyRing};
+use openssl::hash::MessageDigest;
+use serde::{Deserialize, Serialize};
+
+/// Value of the `iss` claim for tokens minted by this service.
+pub const ISSUER: &str = "logistics-management-system";
+/// Value of the `aud` claim for tokens minted by this service.
+pub const AUDIENCE: &str = "logistics-management-system";
+
+// reference: https://auth0.com/docs/secure/tokens/json-web-tokens/json-web-token-claims
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct JWTClaim {
+    #[serde(rename = "iss")]
+    pub issuer: String,
+    #[serde(rename = "sub")]
+    pub subject: sqlx::types::Uuid,
+    #[serde(rename = "aud")]
+    pub audience: String,
+    /// NumericDate, seconds since the unix epoch.
+    #[serde(rename = "exp")]
+    pub expiration: i64,
+    /// NumericDate, seconds since the unix epoch.
+    #[serde(rename = "nbf")]
+    pub not_before: i64,
+    /// NumericDate, seconds since the unix epoch.
+    #[serde(rename = "iat")]
+    pub issued_at: i64,
+    #[serde(rename = "jti")]
+    pub jwt_id: sqlx::types::Uuid,
+    /// Session (refresh token family) the token was issued for.
+    #[serde(rename = "sid", default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<sqlx::types::Uuid>,
+    /// Serialized as the space delimited `scope` claim of RFC 9068.
+    #[serde(
+        rename = "scope",
+        default,
+        with = "scope",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub scopes: Vec<String>,
+    #[serde(rename = "claims", default)]
+    pub claims: BTreeMap<String, String>,
+    /// API key the request was authenticated with, never part of a signed token.
+    #[serde(skip)]
+    pub api_key_id: Option<sqlx::types::Uuid>,
+}
+
+/// Expected values for the registered claims of incoming tokens.
+#[derive(Debug, Clone)]
+pub struct Validation {
+    pub issuer: String,
+    pub audience: String,
+    /// Tolerated clock skew between the issuer and this service.
+    pub leeway: Duration,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            issuer: ISSUER.into(),
+            audience: AUDIENCE.into(),
+            leeway: Duration::from_secs(60),
+        }
+    }
+}
+
+impl JWTClaim {
+    /// Builds the claims of a new access token for `user` valid for `ttl` from now.
+    pub fn issue(user: sqlx::types::Uuid, scopes: Vec<String>, ttl: Duration) -> Self {
+        let now = Utc::now().timestamp();
+
+        Self {
+            issuer: ISSUER.into(),
+            subject: user,
+            audience: AUDIENCE.into(),
+            expiration: now + ttl.as_secs() as i64,
+            not_before: now,
+            issued_at: now,
+            jwt_id: sqlx::types::Uuid::new_v4(),
+            session_id: None,
+            scopes,
+            claims: BTreeMap::new(),
+            api_key_id: None,
+        }
+    }
+
+    /// Signs the claims with the current signing key of `ring`, setting the `kid` header.
+    pub fn sign(self, ring: &KeyRing) -> lib_core::result::Result<String> {
+        let key = ring
+            .signing_key()
+            .ok_or_else(|| Error::Custom("no signing key available".into()))?;
+
+        let header = Header {
+            algorithm: AlgorithmType::Rs256,
+            key_id: Some(key.kid),
+            type_: Some(HeaderType::JsonWebToken),
+            ..Default::default()
+        };
+
+        let token = Token::new(header, self)
+            .sign_with_key(&PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: key.private_key,
+            })
+            .map_err(|err| Error::Custom(err.into()))?;
+
+        Ok(token.as_str().to_string())
+    }
+
+    /// Reads the `kid` header of a token without verifying it.
+    pub fn key_id(token: &str) -> Option<String> {
+        Token::<Header, JWTClaim, _>::parse_unverified(token)
+            .ok()?
+            .header()
+            .key_id
+            .clone()
+    }
+
+    /// Verifies the signature of `token` with the key named by its `kid` header.
+    ///
+    /// Only the signature is checked, call [`JWTClaim::validate`] on the result.
+    pub fn verify(token: &str, ring: &KeyRing) -> lib_core::result::Result<Self> {
+        let token = Token::<Header, JWTClaim, _>::parse_unverified(token)
+            .map_err(|_| Error::AuthenticationError)?;
+
+        let key = token
+            .header()
+            .key_id
+            .as_deref()
+            .and_then(|kid| ring.find(kid))
+            .ok_or(Error::AuthenticationError)?;
+
+        let token: Token<Header, JWTClaim, _> = token
+            .verify_with_key(&PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: key.public_key,
+            })
+            .map_err(|_| Error::AuthenticationError)?;
+
+        Ok(token.claims().clone())
+    }
+
+    /// Checks the registered claims of a token whose signature was already verified.
+    pub fn validate(&self, validation: &Validation) -> lib_core::result::Result<()> {
+        self.validate_at(validation, Utc::now().timestamp())
+    }
+
+    fn validate_at(&self, validation: &Validation, now: i64) -> lib_core::result::Result<()> {
+        let leeway = validation.leeway.as_secs() as i64;
+
+        if self.expiration + leeway <= now
+            || self.not_before - leeway > now
+            || self.issuer != validation.issuer
+            || self.audience != validation.audience
+        {
+            return Err(Error::AuthenticationError);
+        }
+
+        Ok(())
+    }
+}
+
+mod scope {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(scopes: &[String], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&scopes.join(" "))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(String::deserialize(deserializer)?
+            .split_whitespace()
+            .map(String::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_742_000_000;
+
+    fn claim() -> JWTClaim {
+        JWTClaim {
+            issuer: ISSUER.into(),
+            subject: sqlx::types::Uuid::new_v4(),
+            audience: AUDIENCE.into(),
+            expiration: NOW + 3600,
+            not_before: NOW,
+            issued_at: NOW,
+            jwt_id: sqlx::types::Uuid::new_v4(),
+            session_id: Some(sqlx::types::Uuid::new_v4()),
+            scopes: vec!["shipment:read".into(), "shipment:update".into()],
+            claims: BTreeMap::new(),
+            api_key_id: None,
+        }
+    }
+
+    #[test]
+    fn accepts_valid_token() {
+        assert!(claim().validate_at(&Validation::default(), NOW).is_ok());
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let validation = Validation::default();
+        let claim = JWTClaim {
+            expiration: NOW - 120,
+            ..claim()
+        };
+
+        assert!(claim.validate_at(&validation, NOW).is_err());
+
+        // still inside the tolerated clock skew
+        let claim = JWTClaim {
+            expiration: NOW - 30,
+            ..claim
+        };
+
+        assert!(claim.validate_at(&validation, NOW).is_ok());
+    }
+
+    #[test]
+    fn rejects_not_yet_valid_token() {
+        let validation = Validation::default();
+        let claim = JWTClaim {
+            not_before: NOW + 120,
+            ..claim()
+        };
+
+        assert!(claim.validate_at(&validation, NOW).is_err());
+        assert!(
+            claim
+                .validate_at(
+                    &Validation {
+                        leeway: Duration::from_secs(120),
+                        ..validation
+                    },
+                    NOW
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_audience() {
+        let claim = JWTClaim {
+            audience: "another-service".into(),
+            ..claim()
+        };
+
+        assert!(claim.validate_at(&Validation::default(), NOW).is_err());
+    }
+
+    #[test]
+    fn serializes_numeric_dates_and_scope() {
+        let claim = claim();
+        let json = serde_json::to_value(&claim).unwrap();
+
+        assert_eq!(json["exp"], NOW + 3600);
+        assert_eq!(json["nbf"], NOW);
+        assert_eq!(json["scope"], "shipment:read shipment:update");
+        assert_eq!(serde_json::from_value::<JWTClaim>(json).unwrap(), claim);
+    }
+
+    fn key(kid: &str) -> lib_core::keys::Key {
+        let private_key =
+            openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        let public_key =
+            openssl::pkey::PKey::public_key_from_der(&private_key.public_key_to_der().unwrap())
+                .unwrap();
+
+        lib_core::keys::Key {
+            kid: kid.into(),
+            private_key,
+            public_key,
+        }
+    }
+
+    #[test]
+    fn verifies_tokens_signed_by_retired_keys() {
+        let ring = KeyRing::default();
+        ring.replace(vec![key("old")]);
+
+        let token = claim().sign(&ring).unwrap();
+        assert_eq!(JWTClaim::key_id(&token).as_deref(), Some("old"));
+
+        ring.replace(vec![key("new"), ring.find("old").unwrap()]);
+        assert_eq!(JWTClaim::verify(&token, &ring).unwrap().issuer, ISSUER);
+
+        ring.replace(vec![key("new")]);
+        assert!(JWTClaim::verify(&token, &ring).is_err());
+    }
+
+    #[test]
+    fn issue_sets_lifetime() {
+        let claim = JWTClaim::issue(
+            sqlx::types::Uuid::new_v4(),
+            vec![],
+            Duration::from_secs(3600),
+        );
+
+        assert_eq!(claim.expiration - claim.issued_at, 3600);
+        assert_eq!(claim.not_before, claim.issued_at);
+        assert!(claim.validate(&Validation::default()).is_ok());
+    }
+}
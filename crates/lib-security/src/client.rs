@@ -0,0 +1,90 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, header::USER_AGENT, request::Parts},
+};
+use lib_core::AppState;
+
+/// Header appended to by reverse proxies, the last entry is the peer the proxy saw.
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Longest user agent kept, anything beyond is cut off.
+const USER_AGENT_LENGTH: usize = 512;
+
+/// Address and user agent of the client making the request, as far as they are known.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Rightmost `X-Forwarded-For` entry when the proxy is trusted, the peer address otherwise.
+///
+/// Entries left of it are supplied by the client and cannot be trusted.
+fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Option<String> {
+    let forwarded = headers
+        .get_all(FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .rfind(|entry| !entry.is_empty());
+
+    match forwarded {
+        Some(forwarded) if trust_proxy => Some(forwarded.to_string()),
+        _ => peer.map(|peer| peer.ip().to_string()),
+    }
+}
+
+impl FromRequestParts<AppState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| *peer);
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(USER_AGENT_LENGTH).collect());
+
+        Ok(Self {
+            ip: client_ip(&parts.headers, peer, state.config.server.trust_proxy),
+            user_agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_forwarded_address_from_trusted_proxies_only() {
+        let mut headers = HeaderMap::new();
+        headers.append(FORWARDED_FOR, "10.0.0.1, 203.0.113.7".parse().unwrap());
+        headers.append(FORWARDED_FOR, "198.51.100.2".parse().unwrap());
+        let peer = Some(SocketAddr::from(([192, 0, 2, 1], 443)));
+
+        assert_eq!(
+            client_ip(&headers, peer, true).as_deref(),
+            Some("198.51.100.2")
+        );
+        assert_eq!(
+            client_ip(&headers, peer, false).as_deref(),
+            Some("192.0.2.1")
+        );
+        assert_eq!(
+            client_ip(&HeaderMap::new(), peer, true).as_deref(),
+            Some("192.0.2.1")
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), None, true), None);
+    }
+}
@@ -0,0 +1,227 @@
+use std::marker::PhantomData;
+
+use axum::{
+    extract::FromRequestParts, http::request::Parts, middleware::from_extractor_with_state,
+};
+use lib_core::{AppState, error::Error};
+use sea_orm::{ActiveEnum, Condition, EntityTrait, ModelTrait};
+use utoipa::openapi::security::SecurityRequirement;
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouter};
+
+use crate::{
+    JWTClaim, Permission,
+    scope::{self, Scope},
+};
+
+/// Name of the bearer token security scheme registered in `lib_core::docs::OpenAPI`.
+pub const SECURITY_SCHEME: &str = "bearer_auth";
+
+/// A table permissions are granted on.
+pub trait Resource: Send + Sync + 'static {
+    /// `permissions.entity_name` of the resource.
+    const NAME: &'static str;
+}
+
+/// An action permissions are granted for.
+pub trait Action: Send + Sync + 'static {
+    const PERMISSION: Permission;
+}
+
+pub struct Create;
+pub struct Read;
+pub struct Update;
+pub struct Delete;
+
+impl Action for Create {
+    const PERMISSION: Permission = Permission::Create;
+}
+
+impl Action for Read {
+    const PERMISSION: Permission = Permission::Read;
+}
+
+impl Action for Update {
+    const PERMISSION: Permission = Permission::Update;
+}
+
+impl Action for Delete {
+    const PERMISSION: Permission = Permission::Delete;
+}
+
+/// `entity:action` scope naming the permission `A` on `R`.
+pub fn scope<R: Resource, A: Action>() -> String {
+    scope_name(R::NAME, &A::PERMISSION)
+}
+
+/// `entity:action` scope naming `permission` on `table`.
+pub fn scope_name(table: &str, permission: &Permission) -> String {
+    format!("{table}:{}", permission.to_value())
+}
+
+/// Extracts the claims of a user holding the permission `A` on `R` whose token carries the
+/// matching scope, rejecting everyone else with [`Error::AuthorizationError`].
+///
+/// Taken as a handler argument, or as a route layer through [`GuardedRoutes::guarded`].
+pub struct RequirePermission<R, A> {
+    pub claims: JWTClaim,
+    /// Rows of `R` the permission is granted on.
+    pub scopes: Vec<Scope>,
+    marker: PhantomData<(R, A)>,
+}
+
+impl<R, A> RequirePermission<R, A> {
+    /// Filter restricting a query on `E` to the rows the permission is granted on.
+    pub fn condition<E: EntityTrait>(&self) -> Condition {
+        scope::condition::<E>(&self.scopes, self.claims.subject)
+    }
+
+    /// Whether the permission is granted on the already loaded `model`.
+    pub fn permits<M: ModelTrait>(&self, model: &M) -> bool {
+        scope::permits(&self.scopes, self.claims.subject, model)
+    }
+}
+
+impl<R: Resource, A: Action> FromRequestParts<AppState> for RequirePermission<R, A> {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let claims = JWTClaim::from_request_parts(parts, state).await?;
+
+        // the token scope is a ceiling, a token may be issued with less than the user holds
+        if !claims.scopes.contains(&scope::<R, A>()) {
+            return Err(Error::AuthorizationError);
+        }
+
+        // tokens only carry table wide grants in this mode, see `scope::token_scopes`
+        if state.config.jwt.scope_authorization {
+            return Ok(Self {
+                claims,
+                scopes: vec![Scope::All],
+                marker: PhantomData,
+            });
+        }
+
+        let scopes =
+            scope::permitted_scopes(&state.db, &claims, R::NAME, vec![A::PERMISSION]).await?;
+
+        if scopes.is_empty() {
+            return Err(Error::AuthorizationError);
+        }
+
+        Ok(Self {
+            claims,
+            scopes,
+            marker: PhantomData,
+        })
+    }
+}
+
+pub trait GuardedRoutes {
+    /// Adds `routes` behind a [`RequirePermission<R, A>`] route layer and documents the
+    /// bearer token and scope they require.
+    ///
+    /// Route layers run before the router state is provided, hence `state`.
+    fn guarded<R: Resource, A: Action>(
+        self,
+        state: &AppState,
+        routes: UtoipaMethodRouter<AppState>,
+    ) -> Self;
+}
+
+impl GuardedRoutes for OpenApiRouter<AppState> {
+    fn guarded<R: Resource, A: Action>(
+        self,
+        state: &AppState,
+        routes: UtoipaMethodRouter<AppState>,
+    ) -> Self {
+        let mut guarded =
+            OpenApiRouter::new()
+                .routes(routes)
+                .route_layer(from_extractor_with_state::<RequirePermission<R, A>, _>(
+                    state.clone(),
+                ));
+
+        let requirement = SecurityRequirement::new(SECURITY_SCHEME, [scope::<R, A>()]);
+        for item in guarded.get_openapi_mut().paths.paths.values_mut() {
+            for operation in [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.options,
+                &mut item.head,
+                &mut item.patch,
+                &mut item.trace,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                operation
+                    .security
+                    .get_or_insert_with(Vec::new)
+                    .push(requirement.clone());
+            }
+        }
+
+        self.merge(guarded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lib_core::{config::Config, mailer::LogMailer};
+    use sea_orm::DatabaseConnection;
+    use utoipa_axum::routes;
+
+    use super::*;
+
+    struct Shipment;
+
+    impl Resource for Shipment {
+        const NAME: &'static str = "shipment";
+    }
+
+    #[utoipa::path(get, path = "/")]
+    async fn read() {}
+
+    #[utoipa::path(post, path = "/")]
+    async fn create() {}
+
+    #[test]
+    fn documents_required_scope_per_operation() {
+        let state = AppState {
+            config: Arc::new(Config::default()),
+            db: DatabaseConnection::Disconnected,
+            keys: Arc::default(),
+            revocations: Arc::default(),
+            password_params: argon2::Params::default(),
+            mailer: Arc::new(LogMailer),
+            oidc_discovery: Arc::default(),
+            shutdown: Default::default(),
+        };
+
+        let (_, openapi) = OpenApiRouter::<AppState>::new()
+            .guarded::<Shipment, Read>(&state, routes!(read))
+            .guarded::<Shipment, Create>(&state, routes!(create))
+            .split_for_parts();
+
+        let item = openapi.paths.paths.get("/").unwrap();
+        let security = |operation: &Option<utoipa::openapi::path::Operation>| {
+            serde_json::to_value(&operation.as_ref().unwrap().security).unwrap()
+        };
+
+        assert_eq!(
+            security(&item.get),
+            serde_json::json!([{ "bearer_auth": ["shipment:read"] }])
+        );
+        assert_eq!(
+            security(&item.post),
+            serde_json::json!([{ "bearer_auth": ["shipment:create"] }])
+        );
+    }
+}
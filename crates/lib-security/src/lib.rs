@@ -1,140 +1,219 @@
+use audit::Target;
 use axum::extract::FromRequestParts;
-use jwt::VerifyWithKey;
+use axum::http::header::AUTHORIZATION;
+use chrono::Utc;
 use lib_core::{AppState, error::Error};
-use lib_entity::permissions;
-use sea_orm::ActiveModelBehavior;
-use sea_orm::ActiveModelTrait;
+use lib_entity::{permissions, sea_orm_active_enums::PermissionEvent};
 use sea_orm::ColumnTrait;
-use sea_orm::IntoActiveModel;
 use sea_orm::Set;
 use sea_orm::TransactionTrait;
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter};
-use std::collections::BTreeMap;
-
-use serde::{Deserialize, Serialize};
-
-// reference: https://auth0.com/docs/secure/tokens/json-web-tokens/json-web-token-claims
-#[derive(Deserialize, Serialize, Debug)]
-pub struct JWTClaim {
-    #[serde(rename = "iss")]
-    pub issuer: String,
-    #[serde(rename = "sub")]
-    pub subject: sqlx::types::Uuid,
-    #[serde(rename = "aud")]
-    pub audience: String,
-    #[serde(rename = "exp")]
-    pub expiration: String,
-    #[serde(rename = "nbf")]
-    pub not_before: String,
-    #[serde(rename = "iat")]
-    pub issued_at: String,
-    #[serde(rename = "jti")]
-    pub jwt_id: sqlx::types::Uuid,
-    #[serde(rename = "claims")]
-    pub claims: BTreeMap<String, String>,
+use sea_orm::prelude::Uuid;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, TryInsertResult,
+};
+use std::time::Duration;
+
+pub mod api_key;
+pub mod audit;
+pub mod auth_event;
+pub mod claim;
+pub mod client;
+pub mod guard;
+pub mod keys;
+pub mod lockout;
+pub mod oidc;
+pub mod password;
+pub mod revocation;
+pub mod role;
+pub mod scope;
+pub mod secret;
+pub mod totp;
+
+/// Minimum delay between two reloads of the key ring triggered by an unknown `kid`.
+const KEY_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+pub use claim::{AUDIENCE, ISSUER, JWTClaim, Validation};
+pub use scope::Scope;
+
+/// Reads the token from an `Authorization: Bearer <token>` header.
+fn bearer_token(parts: &axum::http::request::Parts) -> lib_core::result::Result<&str> {
+    let header = parts
+        .headers
+        .get(AUTHORIZATION)
+        .ok_or(Error::AuthenticationError)?
+        .to_str()
+        .map_err(|_| Error::AuthenticationError)?;
+
+    let (scheme, token) = header.split_once(' ').ok_or(Error::AuthenticationError)?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return Err(Error::AuthenticationError);
+    }
+
+    Ok(token.trim())
 }
 
-impl<S> FromRequestParts<S> for JWTClaim
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<AppState> for JWTClaim {
     type Rejection = lib_core::error::Error;
 
     async fn from_request_parts(
         parts: &mut axum::http::request::Parts,
-        _: &S,
+        state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let state = parts
-            .extensions
-            .get::<AppState>()
-            .ok_or(Error::AuthenticationError)?;
-
-        let token = parts
-            .headers
-            .get("Authorization")
-            .ok_or(Error::AuthenticationError)?
-            .to_str()
-            .map_err(|_| Error::AuthenticationError)?;
-
-        let claims: JWTClaim = token
-            .verify_with_key(&state.key)
-            .map_err(|_| Error::AuthenticationError)?;
+        let token = bearer_token(parts)?;
+
+        let claims = if api_key::is_api_key(token) {
+            api_key::authenticate(state, token).await?
+        } else {
+            verify_access_token(state, token).await?
+        };
+
+        tracing::Span::current().record("user_id", tracing::field::display(claims.subject));
 
         Ok(claims)
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Permission {
-    Read,
-    Write,
-    Update,
-    Delete,
-}
+/// Claims of a signed, unexpired and unrevoked access token.
+async fn verify_access_token(state: &AppState, token: &str) -> lib_core::result::Result<JWTClaim> {
+    // a key created by another replica may not have been loaded yet
+    let unknown_key = JWTClaim::key_id(token).is_some_and(|kid| state.keys.find(&kid).is_none());
+    if unknown_key && state.keys.age() > KEY_RELOAD_INTERVAL {
+        keys::load(
+            &state.db,
+            &state.keys,
+            state.config.jwt.key_passphrase.as_deref(),
+        )
+        .await?;
+    }
 
-impl Into<sea_orm::Value> for Permission {
-    fn into(self) -> sea_orm::Value {
-        match self {
-            Permission::Read => "read".into(),
-            Permission::Write => "write".into(),
-            Permission::Update => "update".into(),
-            Permission::Delete => "delete".into(),
-        }
+    let claims = JWTClaim::verify(token, &state.keys)?;
+
+    claims.validate(&Validation {
+        leeway: Duration::from_secs(state.config.jwt.leeway),
+        ..Default::default()
+    })?;
+
+    if revocation::is_revoked(state, &claims).await? {
+        return Err(Error::AuthenticationError);
     }
+
+    Ok(claims)
 }
 
-impl Into<String> for Permission {
-    fn into(self) -> String {
-        match self {
-            Permission::Read => "read".into(),
-            Permission::Write => "write".into(),
-            Permission::Update => "update".into(),
-            Permission::Delete => "delete".into(),
-        }
-    }
+/// Action a permission allows on an entity, stored as `logistics.permission_action`.
+pub use lib_entity::sea_orm_active_enums::PermissionAction as Permission;
+
+/// Matches the direct grants of `user_id` on `table` for any of `permissions` in `scope`.
+fn permission_filter(
+    user_id: Uuid,
+    table: &str,
+    permissions: Vec<Permission>,
+    scope: &Scope,
+) -> Condition {
+    Condition::all()
+        .add(permissions::Column::UserId.eq(user_id))
+        .add(permissions::Column::EntityName.eq(table))
+        .add(permissions::Column::Action.is_in(permissions))
+        .add(scope.filter(
+            permissions::Column::ResourceColumn,
+            permissions::Column::ResourceValue,
+        ))
+}
+
+/// Inserts a direct grant, granting twice is a no-op.
+fn insert_permission(
+    user_id: Uuid,
+    granted_by: Uuid,
+    table: &str,
+    permission: Permission,
+    scope: &Scope,
+) -> sea_orm::Insert<permissions::ActiveModel> {
+    let (resource_column, resource_value) = scope.to_columns();
+
+    lib_entity::prelude::Permissions::insert(permissions::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        entity_name: Set(table.to_string()),
+        action: Set(permission),
+        resource_column: Set(resource_column),
+        resource_value: Set(resource_value),
+        granted_by: Set(Some(granted_by)),
+        granted_at: Set(Utc::now().naive_utc()),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            permissions::Column::UserId,
+            permissions::Column::EntityName,
+            permissions::Column::Action,
+            permissions::Column::ResourceColumn,
+            permissions::Column::ResourceValue,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
 }
 
+/// Whether the user holds any of `permissions` on at least some rows of `table`, either
+/// granted directly or through one of their roles.
+///
+/// Use [`scope::permitted_scopes`] to find out which rows.
 pub async fn verify_permission(
     db: &DatabaseConnection,
     claims: &JWTClaim,
     table: &str,
     permissions: Vec<Permission>,
 ) -> lib_core::result::Result<bool> {
-    Ok(lib_entity::prelude::Permissions::find()
-        .filter(permissions::Column::EntityName.eq(table))
-        .filter(permissions::Column::UserId.eq(claims.subject))
-        .filter(permissions::Column::Action.is_in(permissions))
-        .one(db)
+    Ok(!scope::permitted_scopes(db, claims, table, permissions)
+        .await?
+        .is_empty())
+}
+
+/// Direct grants of `user_id`, role grants are listed by [`role::role_permissions`].
+pub async fn user_permissions(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+) -> lib_core::result::Result<Vec<permissions::Model>> {
+    lib_entity::prelude::Permissions::find()
+        .filter(permissions::Column::UserId.eq(user_id))
+        .order_by_asc(permissions::Column::EntityName)
+        .all(db)
         .await
-        .map_err(Error::SeaOrm)?
-        .is_some())
+        .map_err(Error::SeaOrm)
 }
 
+/// Grants `permissions` on `table` to `user_id` on behalf of `actor`, recording every new
+/// grant in the audit trail.
 pub async fn grant_permission(
     db: &DatabaseConnection,
-    claims: &JWTClaim,
+    actor: &JWTClaim,
+    user_id: Uuid,
     table: &str,
     permissions: Vec<Permission>,
+    scope: &Scope,
 ) -> lib_core::result::Result<()> {
+    scope::validate(table, scope)?;
+
     let trx = db.begin().await.map_err(Error::SeaOrm)?;
+
     for perm in permissions.into_iter() {
-        let model = lib_entity::prelude::Permissions::find()
-            .filter(permissions::Column::UserId.eq(claims.subject))
-            .filter(permissions::Column::Action.eq(perm.clone()))
-            .filter(permissions::Column::EntityName.eq(table))
-            .one(db)
+        let inserted = insert_permission(user_id, actor.subject, table, perm.clone(), scope)
+            .do_nothing()
+            .exec(&trx)
             .await
             .map_err(Error::SeaOrm)?;
 
-        if model.is_some() {
-            continue;
+        if let TryInsertResult::Inserted(_) = inserted {
+            audit::record(
+                &trx,
+                actor,
+                PermissionEvent::Grant,
+                Target::User(user_id),
+                Some((table, perm, scope)),
+            )
+            .await?;
         }
-
-        let mut model = permissions::ActiveModel::new();
-        model.entity_name = Set(table.to_string());
-        model.user_id = Set(claims.subject);
-        model.action = Set(perm.into());
-        _ = model.insert(&trx).await.map_err(Error::SeaOrm)?;
     }
 
     trx.commit().await.map_err(Error::SeaOrm)?;
@@ -142,32 +221,107 @@ pub async fn grant_permission(
     Ok(())
 }
 
+/// Revokes `permissions` on `table` from `user_id` on behalf of `actor`, recording every
+/// removed grant in the audit trail.
 pub async fn revoke_permission(
     db: &DatabaseConnection,
-    claims: &JWTClaim,
+    actor: &JWTClaim,
+    user_id: Uuid,
     table: &str,
     permissions: Vec<Permission>,
+    scope: &Scope,
 ) -> lib_core::result::Result<()> {
     let trx = db.begin().await.map_err(Error::SeaOrm)?;
+
     for perm in permissions.into_iter() {
-        let model = lib_entity::prelude::Permissions::find()
-            .filter(permissions::Column::UserId.eq(claims.subject))
-            .filter(permissions::Column::Action.eq(perm))
-            .filter(permissions::Column::EntityName.eq(table))
-            .one(db)
+        let deleted = lib_entity::prelude::Permissions::delete_many()
+            .filter(permission_filter(user_id, table, vec![perm.clone()], scope))
+            .exec(&trx)
             .await
             .map_err(Error::SeaOrm)?;
 
-        if model.is_none() {
-            continue;
+        if deleted.rows_affected > 0 {
+            audit::record(
+                &trx,
+                actor,
+                PermissionEvent::Revoke,
+                Target::User(user_id),
+                Some((table, perm, scope)),
+            )
+            .await?;
         }
-
-        let model = model.ok_or(Error::RowNotFound)?.into_active_model();
-
-        _ = model.delete(&trx).await.map_err(Error::SeaOrm)?;
     }
 
     trx.commit().await.map_err(Error::SeaOrm)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{ActiveEnum, DbBackend, QueryTrait};
+
+    #[test]
+    fn permission_maps_to_database_enum() {
+        for (permission, value) in [
+            (Permission::Create, "create"),
+            (Permission::Read, "read"),
+            (Permission::Update, "update"),
+            (Permission::Delete, "delete"),
+        ] {
+            assert_eq!(permission.to_value(), value);
+            assert_eq!(
+                Permission::try_from_value(&value.into()).unwrap(),
+                permission
+            );
+        }
+
+        assert!(Permission::try_from_value(&"write".into()).is_err());
+    }
+
+    /// Query shape only, the statements are built but never run against a database.
+    #[test]
+    fn grant_verify_and_revoke_queries_address_the_same_row() {
+        let user = Uuid::new_v4();
+
+        let actor = Uuid::new_v4();
+        let grant = insert_permission(user, actor, "shipment", Permission::Create, &Scope::All)
+            .build(DbBackend::Postgres)
+            .to_string();
+        let verify = lib_entity::prelude::Permissions::find()
+            .filter(permission_filter(
+                user,
+                "shipment",
+                vec![Permission::Create],
+                &Scope::All,
+            ))
+            .build(DbBackend::Postgres)
+            .to_string();
+        let revoke = lib_entity::prelude::Permissions::delete_many()
+            .filter(permission_filter(
+                user,
+                "shipment",
+                vec![Permission::Create],
+                &Scope::All,
+            ))
+            .build(DbBackend::Postgres)
+            .to_string();
+
+        let action = "CAST('create' AS permission_action)";
+        let user = format!("'{user}'");
+
+        assert!(grant.starts_with(r#"INSERT INTO "logistics"."permissions""#));
+        assert!(grant.contains(&format!(
+            "{user}, 'shipment', {action}, NULL, NULL, '{actor}', "
+        )));
+        assert!(grant.ends_with(r#"ON CONFLICT ("user_id", "entity_name", "action", "resource_column", "resource_value") DO NOTHING"#));
+
+        // verify and revoke match exactly the row the grant wrote
+        let filter = format!(
+            r#"WHERE "permissions"."user_id" = {user} AND "permissions"."entity_name" = 'shipment' AND "permissions"."action" IN ({action}) AND ("permissions"."resource_column" IS NULL AND "permissions"."resource_value" IS NULL)"#
+        );
+        assert!(verify.starts_with("SELECT") && verify.ends_with(&filter));
+        assert!(revoke.starts_with("DELETE") && revoke.ends_with(&filter));
+    }
+}
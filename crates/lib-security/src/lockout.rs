@@ -0,0 +1,302 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use lib_core::{error::Error, result::Result};
+use lib_entity::{login_throttle, prelude::LoginThrottle};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QuerySelect, Set, TransactionTrait, sea_query::OnConflict,
+};
+
+/// How failed logins of one counter are slowed down and eventually locked out.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Failures answered without delay.
+    pub free_attempts: i32,
+    /// Delay after the first failure beyond the free ones, doubled with every further one.
+    pub backoff: TimeDelta,
+    /// Upper bound of the delay between two attempts.
+    pub max_backoff: TimeDelta,
+    /// Failures locking the counter out for `lockout`.
+    pub lock_after: i32,
+    pub lockout: TimeDelta,
+    /// Failures older than this are forgotten.
+    pub window: TimeDelta,
+}
+
+/// Failures on one account, whatever address they come from.
+pub const ACCOUNT: Policy = Policy {
+    free_attempts: 5,
+    backoff: TimeDelta::seconds(1),
+    max_backoff: TimeDelta::seconds(60),
+    lock_after: 10,
+    lockout: TimeDelta::minutes(15),
+    window: TimeDelta::hours(1),
+};
+
+/// Failures from one address, whatever accounts they target.
+pub const IP: Policy = Policy {
+    free_attempts: 20,
+    backoff: TimeDelta::seconds(1),
+    max_backoff: TimeDelta::seconds(60),
+    lock_after: 50,
+    lockout: TimeDelta::minutes(15),
+    window: TimeDelta::hours(1),
+};
+
+/// A failed login counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// Normalized email the login was attempted for, the account need not exist.
+    Account(String),
+    /// Address of the client.
+    Ip(String),
+}
+
+impl Key {
+    pub fn account(email: &str) -> Self {
+        Self::Account(email.trim().to_lowercase())
+    }
+
+    pub fn ip(address: &str) -> Self {
+        Self::Ip(address.to_string())
+    }
+
+    /// `(kind, subject)` primary key of the counter.
+    fn columns(&self) -> (&'static str, &str) {
+        match self {
+            Self::Account(email) => ("account", email),
+            Self::Ip(address) => ("ip", address),
+        }
+    }
+
+    fn policy(&self) -> &'static Policy {
+        match self {
+            Self::Account(_) => &ACCOUNT,
+            Self::Ip(_) => &IP,
+        }
+    }
+
+    fn filter(&self) -> Condition {
+        let (kind, subject) = self.columns();
+
+        Condition::all()
+            .add(login_throttle::Column::Kind.eq(kind))
+            .add(login_throttle::Column::Subject.eq(subject))
+    }
+}
+
+/// Failures of `counter` still counted at `now`.
+fn failures(policy: &Policy, counter: &login_throttle::Model, now: NaiveDateTime) -> i32 {
+    if now - counter.last_failure_at >= policy.window {
+        0
+    } else {
+        counter.failures
+    }
+}
+
+/// Time left until `counter` accepts the next attempt, `None` when it may be made now.
+fn retry_after(
+    policy: &Policy,
+    counter: &login_throttle::Model,
+    now: NaiveDateTime,
+) -> Option<TimeDelta> {
+    if let Some(locked_until) = counter.locked_until.filter(|until| *until > now) {
+        return Some(locked_until - now);
+    }
+
+    let excess = failures(policy, counter, now) - policy.free_attempts;
+    if excess <= 0 {
+        return None;
+    }
+
+    let delay = policy
+        .backoff
+        .checked_mul(1 << (excess - 1).min(16))
+        .unwrap_or(policy.max_backoff)
+        .min(policy.max_backoff);
+
+    Some(counter.last_failure_at + delay - now).filter(|left| *left > TimeDelta::zero())
+}
+
+/// Counts one more failure on `counter`, returns whether it locked it out.
+fn register_failure(
+    policy: &Policy,
+    counter: &mut login_throttle::Model,
+    now: NaiveDateTime,
+) -> bool {
+    counter.failures = failures(policy, counter, now) + 1;
+    counter.last_failure_at = now;
+
+    let locked = counter.failures >= policy.lock_after;
+    if locked {
+        counter.locked_until = Some(now + policy.lockout);
+    }
+
+    locked
+}
+
+/// Rejects the attempt with [`Error::TooManyRequests`] while any of `keys` is backing off
+/// or locked out.
+pub async fn check(db: &DatabaseConnection, keys: &[Key]) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    let counters = LoginThrottle::find()
+        .filter(
+            keys.iter()
+                .fold(Condition::any(), |any, key| any.add(key.filter())),
+        )
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let wait = keys
+        .iter()
+        .filter_map(|key| {
+            let (kind, subject) = key.columns();
+            let counter = counters
+                .iter()
+                .find(|counter| counter.kind == kind && counter.subject == subject)?;
+
+            retry_after(key.policy(), counter, now)
+        })
+        .max();
+
+    match wait {
+        // rounded up, retrying right at the announced time must succeed
+        Some(wait) => Err(Error::TooManyRequests(
+            (wait.num_milliseconds() as u64).div_ceil(1000),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Counts a failed login on `key`, returns whether it locked the key out.
+pub async fn record_failure(db: &DatabaseConnection, key: &Key) -> Result<bool> {
+    let now = Utc::now().naive_utc();
+    let (kind, subject) = key.columns();
+
+    let trx = db.begin().await.map_err(Error::SeaOrm)?;
+
+    LoginThrottle::insert(login_throttle::ActiveModel {
+        kind: Set(kind.to_string()),
+        subject: Set(subject.to_string()),
+        failures: Set(0),
+        last_failure_at: Set(now),
+        locked_until: Set(None),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            login_throttle::Column::Kind,
+            login_throttle::Column::Subject,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .do_nothing()
+    .exec(&trx)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    // concurrent failures are serialized so none of them is lost
+    let mut counter = LoginThrottle::find()
+        .filter(key.filter())
+        .lock_exclusive()
+        .one(&trx)
+        .await
+        .map_err(Error::SeaOrm)?
+        .ok_or_else(|| Error::Custom("login throttle row vanished".into()))?;
+
+    let locked = register_failure(key.policy(), &mut counter, now);
+
+    let mut model = counter.clone().into_active_model();
+    model.failures = Set(counter.failures);
+    model.last_failure_at = Set(counter.last_failure_at);
+    model.locked_until = Set(counter.locked_until);
+    model.update(&trx).await.map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(locked)
+}
+
+/// Forgets the failures on `key` and lifts its lockout, returns whether there were any.
+pub async fn reset(db: &DatabaseConnection, key: &Key) -> Result<bool> {
+    let deleted = LoginThrottle::delete_many()
+        .filter(key.filter())
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(deleted.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(now: NaiveDateTime) -> login_throttle::Model {
+        login_throttle::Model {
+            kind: "account".into(),
+            subject: "johndoe@email.com".into(),
+            failures: 0,
+            last_failure_at: now,
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_then_locks_out() {
+        let now = Utc::now().naive_utc();
+        let mut counter = counter(now);
+
+        for _ in 0..ACCOUNT.free_attempts {
+            assert!(!register_failure(&ACCOUNT, &mut counter, now));
+            assert_eq!(retry_after(&ACCOUNT, &counter, now), None);
+        }
+
+        for delay in [1, 2, 4, 8] {
+            assert!(!register_failure(&ACCOUNT, &mut counter, now));
+            assert_eq!(
+                retry_after(&ACCOUNT, &counter, now),
+                Some(TimeDelta::seconds(delay))
+            );
+            // the delay runs from the last failure
+            assert_eq!(
+                retry_after(&ACCOUNT, &counter, now + TimeDelta::seconds(delay)),
+                None
+            );
+        }
+
+        assert!(register_failure(&ACCOUNT, &mut counter, now));
+        assert_eq!(counter.failures, ACCOUNT.lock_after);
+        assert_eq!(retry_after(&ACCOUNT, &counter, now), Some(ACCOUNT.lockout));
+    }
+
+    #[test]
+    fn caps_the_backoff() {
+        let now = Utc::now().naive_utc();
+        let mut counter = counter(now);
+        counter.failures = IP.lock_after - 1;
+
+        assert_eq!(retry_after(&IP, &counter, now), Some(IP.max_backoff));
+    }
+
+    #[test]
+    fn forgets_failures_outside_the_window() {
+        let now = Utc::now().naive_utc();
+        let mut counter = counter(now - ACCOUNT.window);
+        counter.failures = ACCOUNT.lock_after - 1;
+        counter.locked_until = Some(now - TimeDelta::minutes(1));
+
+        assert_eq!(retry_after(&ACCOUNT, &counter, now), None);
+        assert!(!register_failure(&ACCOUNT, &mut counter, now));
+        assert_eq!(counter.failures, 1);
+    }
+
+    #[test]
+    fn normalizes_account_keys() {
+        assert_eq!(
+            Key::account(" JohnDoe@Email.com "),
+            Key::account("johndoe@email.com")
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Relying party side of the OpenID Connect authorization code flow with PKCE.
+
+use std::{sync::LazyLock, time::Duration};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use jwt::{AlgorithmType, Header, PKeyWithDigest, Token, VerifyWithKey};
+use lib_core::{
+    error::Error,
+    oidc::{DiscoveryCache, OidcProvider},
+    result::Result,
+};
+use openssl::{bn::BigNum, hash::MessageDigest, pkey::PKey, rsa::Rsa};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::secret;
+
+/// Scopes requested from the provider, `email` is needed to link existing accounts.
+const SCOPES: &str = "openid email profile";
+
+/// How long the discovery document of the provider is reused before being read again.
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Connections to the provider are pooled across logins.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+fn custom(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Custom(Box::new(err))
+}
+
+pub use lib_core::oidc::Metadata;
+
+/// Verified claims of an ID token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdToken {
+    #[serde(rename = "iss")]
+    pub issuer: String,
+    /// Identifier of the user at the provider, unique per issuer.
+    #[serde(rename = "sub")]
+    pub subject: String,
+    #[serde(rename = "aud", deserialize_with = "audience")]
+    pub audience: Vec<String>,
+    /// NumericDate, seconds since the unix epoch.
+    #[serde(rename = "exp")]
+    pub expiration: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+/// `aud` is either a single string or an array of them.
+fn audience<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(audience) => vec![audience],
+        Audience::Many(audiences) => audiences,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct KeySet {
+    keys: Vec<ProviderKey>,
+}
+
+/// Signing key published by the provider, only RSA keys are used.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderKey {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+impl ProviderKey {
+    fn public_key(&self) -> Result<PKey<openssl::pkey::Public>> {
+        let component = |value: &Option<String>| -> Result<BigNum> {
+            let bytes = URL_SAFE_NO_PAD
+                .decode(value.as_deref().ok_or(Error::AuthenticationError)?)
+                .map_err(|_| Error::AuthenticationError)?;
+            BigNum::from_slice(&bytes).map_err(custom)
+        };
+
+        let rsa = Rsa::from_public_components(component(&self.n)?, component(&self.e)?)
+            .map_err(custom)?;
+
+        PKey::from_rsa(rsa).map_err(custom)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+async fn fetch<T: DeserializeOwned>(url: &str) -> Result<T> {
+    CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(custom)?
+        .json()
+        .await
+        .map_err(custom)
+}
+
+/// Reads the discovery document of `provider`.
+pub async fn discover(provider: &OidcProvider) -> Result<Metadata> {
+    let metadata: Metadata = fetch(&format!(
+        "{}/.well-known/openid-configuration",
+        provider.issuer.trim_end_matches('/')
+    ))
+    .await?;
+
+    // OpenID Connect Discovery 1.0 section 4.3, the document must name the issuer asked for
+    if metadata.issuer != provider.issuer {
+        return Err(Error::Custom(
+            format!(
+                "discovery document of {} names another issuer",
+                provider.issuer
+            )
+            .into(),
+        ));
+    }
+
+    Ok(metadata)
+}
+
+/// [`discover`] through `cache`, the document is only read again once older than
+/// [`DISCOVERY_TTL`].
+pub async fn metadata(provider: &OidcProvider, cache: &DiscoveryCache) -> Result<Metadata> {
+    if let Some(metadata) = cache.get() {
+        return Ok(metadata);
+    }
+
+    let metadata = discover(provider).await?;
+    cache.insert(metadata.clone(), DISCOVERY_TTL);
+
+    Ok(metadata)
+}
+
+/// Generates a PKCE (RFC 7636) code verifier, kept until the callback, and its `S256`
+/// challenge sent with the authorization request.
+pub fn pkce() -> (String, String) {
+    let verifier = secret::generate_secret();
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+    (verifier, challenge)
+}
+
+/// Url of the provider the browser is sent to for signing in.
+pub fn authorization_url(
+    provider: &OidcProvider,
+    metadata: &Metadata,
+    state: &str,
+    nonce: &str,
+    challenge: &str,
+) -> Result<String> {
+    let mut url = Url::parse(&metadata.authorization_endpoint).map_err(custom)?;
+
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", SCOPES)
+        .append_pair("state", state)
+        .append_pair("nonce", nonce)
+        .append_pair("code_challenge", challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(url.into())
+}
+
+/// Redeems the authorization `code` returned to the callback and verifies the ID token it
+/// is exchanged for, which must carry the `nonce` of the authorization request.
+pub async fn authenticate(
+    provider: &OidcProvider,
+    metadata: &Metadata,
+    code: &str,
+    verifier: &str,
+    nonce: &str,
+    leeway: Duration,
+) -> Result<IdToken> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &provider.redirect_uri),
+        ("client_id", &provider.client_id),
+        ("code_verifier", verifier),
+    ];
+    if let Some(client_secret) = &provider.client_secret {
+        form.push(("client_secret", client_secret));
+    }
+
+    let response = CLIENT
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(custom)?;
+
+    // an invalid, reused or expired code, or a verifier not matching the challenge
+    if !response.status().is_success() {
+        return Err(Error::AuthenticationError);
+    }
+
+    let id_token = response
+        .json::<TokenResponse>()
+        .await
+        .map_err(custom)?
+        .id_token;
+    let keys = fetch::<KeySet>(&metadata.jwks_uri).await?.keys;
+
+    verify(
+        provider,
+        &keys,
+        &id_token,
+        nonce,
+        leeway,
+        Utc::now().timestamp(),
+    )
+}
+
+/// Checks the signature and the claims of an ID token, OpenID Connect Core 1.0 section
+/// 3.1.3.7.
+pub fn verify(
+    provider: &OidcProvider,
+    keys: &[ProviderKey],
+    token: &str,
+    nonce: &str,
+    leeway: Duration,
+    now: i64,
+) -> Result<IdToken> {
+    let token = Token::<Header, IdToken, _>::parse_unverified(token)
+        .map_err(|_| Error::AuthenticationError)?;
+
+    if token.header().algorithm != AlgorithmType::Rs256 {
+        return Err(Error::AuthenticationError);
+    }
+
+    let kid = token.header().key_id.as_deref();
+    let key = keys
+        .iter()
+        .filter(|key| key.kty == "RSA")
+        .find(|key| kid.is_none() || key.kid.as_deref() == kid)
+        .ok_or(Error::AuthenticationError)?;
+
+    let token: Token<Header, IdToken, _> = token
+        .verify_with_key(&PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: key.public_key()?,
+        })
+        .map_err(|_| Error::AuthenticationError)?;
+    let claims = token.claims();
+
+    if claims.issuer != provider.issuer
+        || !claims.audience.contains(&provider.client_id)
+        || claims.expiration + leeway.as_secs() as i64 <= now
+        || claims.nonce.as_deref() != Some(nonce)
+    {
+        return Err(Error::AuthenticationError);
+    }
+
+    Ok(claims.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{Form, Json, Router, extract::State, http::StatusCode, routing};
+    use jwt::SignWithKey;
+    use lib_core::keys::{Key, KeyRing};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "logistics";
+    const CODE: &str = "authorization-code";
+
+    /// Challenge and nonce the mock issuer received with the authorization request.
+    #[derive(Clone, Default)]
+    struct Issuer {
+        url: String,
+        key: Option<Key>,
+        request: Arc<Mutex<Option<(String, String)>>>,
+    }
+
+    impl Issuer {
+        fn id_token(&self, claims: Value) -> String {
+            let key = self.key.clone().unwrap();
+            let header = Header {
+                algorithm: AlgorithmType::Rs256,
+                key_id: Some(key.kid),
+                ..Default::default()
+            };
+
+            Token::new(header, claims)
+                .sign_with_key(&PKeyWithDigest {
+                    digest: MessageDigest::sha256(),
+                    key: key.private_key,
+                })
+                .unwrap()
+                .as_str()
+                .to_string()
+        }
+
+        fn claims(&self, nonce: &str) -> Value {
+            json!({
+                "iss": self.url,
+                "sub": "248289761001",
+                "aud": CLIENT_ID,
+                "exp": Utc::now().timestamp() + 300,
+                "iat": Utc::now().timestamp(),
+                "nonce": nonce,
+                "email": "johndoe@email.com",
+                "email_verified": true,
+            })
+        }
+    }
+
+    async fn discovery(State(issuer): State<Issuer>) -> Json<Value> {
+        Json(json!({
+            "issuer": issuer.url,
+            "authorization_endpoint": format!("{}/authorize", issuer.url),
+            "token_endpoint": format!("{}/token", issuer.url),
+            "jwks_uri": format!("{}/jwks", issuer.url),
+        }))
+    }
+
+    async fn jwks(State(issuer): State<Issuer>) -> Json<crate::keys::Jwks> {
+        let ring = KeyRing::default();
+        ring.replace(vec![issuer.key.clone().unwrap()]);
+
+        Json(crate::keys::jwks(&ring).unwrap())
+    }
+
+    async fn token(
+        State(issuer): State<Issuer>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> std::result::Result<Json<Value>, StatusCode> {
+        let (challenge, nonce) = issuer.request.lock().unwrap().clone().unwrap();
+        let verified = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+
+        if form["grant_type"] != "authorization_code"
+            || form["code"] != CODE
+            || form["client_id"] != CLIENT_ID
+            || verified != challenge
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        Ok(Json(json!({
+            "access_token": "provider-access-token",
+            "token_type": "Bearer",
+            "id_token": issuer.id_token(issuer.claims(&nonce)),
+        })))
+    }
+
+    /// Serves a discovery document, a key set and a token endpoint on a random local port.
+    async fn mock_issuer() -> (Issuer, OidcProvider) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let public_key =
+            PKey::public_key_from_der(&private_key.public_key_to_der().unwrap()).unwrap();
+
+        let issuer = Issuer {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            key: Some(Key {
+                kid: "mock".into(),
+                private_key,
+                public_key,
+            }),
+            ..Default::default()
+        };
+
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", routing::get(discovery))
+            .route("/jwks", routing::get(jwks))
+            .route("/token", routing::post(token))
+            .with_state(issuer.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let provider = OidcProvider {
+            issuer: issuer.url.clone(),
+            client_id: CLIENT_ID.into(),
+            client_secret: None,
+            redirect_uri: "http://localhost:8000/api/v1/auth/oidc/callback".into(),
+        };
+
+        (issuer, provider)
+    }
+
+    #[tokio::test]
+    async fn signs_in_with_authorization_code_and_pkce() {
+        let (issuer, provider) = mock_issuer().await;
+        let cache = DiscoveryCache::default();
+        let metadata = super::metadata(&provider, &cache).await.unwrap();
+        assert_eq!(cache.get().unwrap().token_endpoint, metadata.token_endpoint);
+
+        let (verifier, challenge) = pkce();
+        let url = authorization_url(&provider, &metadata, "state", "nonce", &challenge).unwrap();
+        let query: HashMap<_, _> = Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(query["redirect_uri"], provider.redirect_uri);
+
+        // the browser signs in at the provider which remembers the authorization request
+        *issuer.request.lock().unwrap() =
+            Some((query["code_challenge"].clone(), query["nonce"].clone()));
+
+        let id_token = authenticate(
+            &provider,
+            &metadata,
+            CODE,
+            &verifier,
+            "nonce",
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert_eq!(id_token.subject, "248289761001");
+        assert_eq!(id_token.email.as_deref(), Some("johndoe@email.com"));
+        assert!(id_token.email_verified);
+
+        // a stolen code is useless without the verifier
+        let (other, _) = pkce();
+        assert!(
+            authenticate(&provider, &metadata, CODE, &other, "nonce", Duration::ZERO)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_id_tokens() {
+        let (issuer, provider) = mock_issuer().await;
+        let keys = fetch::<KeySet>(&format!("{}/jwks", issuer.url))
+            .await
+            .unwrap()
+            .keys;
+        let now = Utc::now().timestamp();
+        let verify = |claims: Value, nonce: &str| {
+            verify(
+                &provider,
+                &keys,
+                &issuer.id_token(claims),
+                nonce,
+                Duration::ZERO,
+                now,
+            )
+        };
+
+        assert!(verify(issuer.claims("nonce"), "nonce").is_ok());
+        assert!(verify(issuer.claims("nonce"), "replayed").is_err());
+
+        let mut claims = issuer.claims("nonce");
+        claims["aud"] = json!(["another-client"]);
+        assert!(verify(claims, "nonce").is_err());
+
+        let mut claims = issuer.claims("nonce");
+        claims["iss"] = json!("https://attacker.example");
+        assert!(verify(claims, "nonce").is_err());
+
+        let mut claims = issuer.claims("nonce");
+        claims["exp"] = json!(now - 1);
+        assert!(verify(claims, "nonce").is_err());
+    }
+}
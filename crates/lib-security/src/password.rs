@@ -0,0 +1,172 @@
+use std::sync::OnceLock;
+
+use argon2::{
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use lib_core::{error::Error, result::Result};
+
+/// Outcome of checking a password against a stored hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password matches but the hash was produced with other parameters and should be
+    /// replaced by [`hash`] while the plain password is at hand.
+    NeedsRehash,
+}
+
+fn argon2(params: &Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+}
+
+/// Runs `f` on the blocking pool, Argon2id spends tens of milliseconds of CPU that would
+/// otherwise stall every request scheduled on the same worker thread.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Custom(Box::new(err)))
+}
+
+/// Hashes `password` with Argon2id into a PHC string suitable for `users._password`.
+pub async fn hash(params: &Params, password: &str) -> Result<String> {
+    let (params, password) = (params.clone(), password.to_string());
+
+    blocking(move || hash_blocking(&params, &password)).await?
+}
+
+/// Checks `password` against a stored PHC string. Malformed hashes never verify.
+pub async fn verify(params: &Params, password: &str, hash: &str) -> Result<Verification> {
+    let (params, password, hash) = (params.clone(), password.to_string(), hash.to_string());
+
+    blocking(move || verify_blocking(&params, &password, &hash)).await
+}
+
+/// Spends as long as [`verify`] on a real hash, for attempts naming no account, so response
+/// times do not tell which emails are registered.
+///
+/// The dummy hash is produced once, with the `params` of the first call.
+pub async fn verify_dummy(params: &Params, password: &str) -> Result<()> {
+    let (params, password) = (params.clone(), password.to_string());
+
+    blocking(move || verify_dummy_blocking(&params, &password)).await
+}
+
+fn hash_blocking(params: &Params, password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(argon2(params)
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| Error::Custom(err.to_string().into()))?
+        .to_string())
+}
+
+fn verify_blocking(params: &Params, password: &str, hash: &str) -> Verification {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        // accounts without a usable password take as long to reject as the others
+        verify_dummy_blocking(params, password);
+        return Verification::Invalid;
+    };
+
+    // the stored hash carries its own algorithm and parameters
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_err()
+    {
+        return Verification::Invalid;
+    }
+
+    let current = hash.algorithm == Algorithm::Argon2id.ident()
+        && hash.version == Some(Version::V0x13.into())
+        && Params::try_from(&hash).is_ok_and(|stored| {
+            stored.m_cost() == params.m_cost()
+                && stored.t_cost() == params.t_cost()
+                && stored.p_cost() == params.p_cost()
+        });
+
+    if current {
+        Verification::Valid
+    } else {
+        Verification::NeedsRehash
+    }
+}
+
+fn verify_dummy_blocking(params: &Params, password: &str) {
+    static DUMMY: OnceLock<Option<String>> = OnceLock::new();
+
+    let dummy = DUMMY.get_or_init(|| hash_blocking(params, "dummy password never matched").ok());
+    if let Some(dummy) = dummy
+        && let Ok(dummy) = PasswordHash::new(dummy)
+    {
+        let _ = Argon2::default().verify_password(password.as_bytes(), &dummy);
+    }
+}
+
+/// Password complexity policy, usable as `#[garde(custom(lib_security::password::policy))]`.
+///
+/// Passwords must be 9 to 128 characters long and contain an uppercase letter, a digit and a
+/// symbol.
+pub fn policy(value: &str, _: &()) -> garde::Result {
+    let length = value.chars().count();
+
+    if !(9..=128).contains(&length) {
+        return Err(garde::Error::new(
+            "must be between 9 and 128 characters long",
+        ));
+    }
+
+    if !value.chars().any(char::is_uppercase) {
+        return Err(garde::Error::new("must contain an uppercase letter"));
+    }
+
+    if !value.chars().any(|c| c.is_ascii_digit()) {
+        return Err(garde::Error::new("must contain a digit"));
+    }
+
+    if value.chars().all(char::is_alphanumeric) {
+        return Err(garde::Error::new("must contain a symbol"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(m_cost: u32) -> Params {
+        Params::new(m_cost, 1, 1, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn verify_requests_rehash_on_parameter_change() {
+        let hash = hash(&params(8), "RandomPassword1!").await.unwrap();
+        let verify = async |params, password, hash| verify(&params, password, hash).await.unwrap();
+
+        assert_eq!(
+            verify(params(8), "RandomPassword1!", &hash).await,
+            Verification::Valid
+        );
+        assert_eq!(
+            verify(params(16), "RandomPassword1!", &hash).await,
+            Verification::NeedsRehash
+        );
+        assert_eq!(
+            verify(params(8), "WrongPassword1!", &hash).await,
+            Verification::Invalid
+        );
+        assert_eq!(
+            verify(params(8), "!", lib_entity::extensions::UNUSABLE_PASSWORD).await,
+            Verification::Invalid
+        );
+    }
+
+    #[test]
+    fn policy_mirrors_previous_database_constraint() {
+        assert!(policy("RandomPassword1!", &()).is_ok());
+        assert!(policy("Short1!", &()).is_err());
+        assert!(policy("randompassword1!", &()).is_err());
+        assert!(policy("RandomPassword!", &()).is_err());
+        assert!(policy("RandomPassword1", &()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use lib_core::{AppState, error::Error, result::Result};
+use lib_entity::{
+    api_key,
+    prelude::{ApiKey, RefreshToken, RevokedToken, Session},
+    refresh_token, revoked_token, session,
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QuerySelect, Set, TransactionTrait,
+    prelude::Uuid, sea_query::Expr, sea_query::OnConflict,
+};
+
+use crate::JWTClaim;
+
+/// How long a "not revoked" answer is trusted before asking the database again. Bounds the
+/// delay for revocations made by another replica to take effect.
+const NOT_REVOKED_TTL: Duration = Duration::from_secs(30);
+
+/// Remaining lifetime of the token, revoked entries are useless to cache past it.
+fn remaining(claims: &JWTClaim) -> Duration {
+    Duration::from_secs((claims.expiration - Utc::now().timestamp()).max(0) as u64)
+}
+
+/// Whether the token itself or the session it belongs to was revoked.
+pub async fn is_revoked(state: &AppState, claims: &JWTClaim) -> Result<bool> {
+    let ids = std::iter::once(claims.jwt_id).chain(claims.session_id);
+
+    if ids
+        .clone()
+        .any(|id| state.revocations.get(&id) == Some(true))
+    {
+        return Ok(true);
+    }
+
+    if ids
+        .clone()
+        .all(|id| state.revocations.get(&id) == Some(false))
+    {
+        return Ok(false);
+    }
+
+    let token_revoked = RevokedToken::find_by_id(claims.jwt_id)
+        .one(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .is_some();
+
+    let session_revoked = match claims.session_id {
+        Some(session_id) => Session::find_by_id(session_id)
+            .one(&state.db)
+            .await
+            .map_err(Error::SeaOrm)?
+            .is_none_or(|session| session.revoked_at.is_some()),
+        None => false,
+    };
+
+    for (id, revoked) in std::iter::once((claims.jwt_id, token_revoked))
+        .chain(claims.session_id.map(|id| (id, session_revoked)))
+    {
+        let ttl = if revoked {
+            remaining(claims)
+        } else {
+            NOT_REVOKED_TTL
+        };
+        state.revocations.insert(id, revoked, ttl);
+    }
+
+    Ok(token_revoked || session_revoked)
+}
+
+/// Revokes a single access token until it expires.
+pub async fn revoke_token(state: &AppState, claims: &JWTClaim) -> Result<()> {
+    let expires_at = DateTime::from_timestamp(claims.expiration, 0)
+        .ok_or(Error::AuthenticationError)?
+        .naive_utc();
+
+    RevokedToken::insert(revoked_token::ActiveModel {
+        jti: Set(claims.jwt_id),
+        user_id: Set(claims.subject),
+        expires_at: Set(expires_at),
+        revoked_at: Set(Utc::now().naive_utc()),
+    })
+    .on_conflict(
+        OnConflict::column(revoked_token::Column::Jti)
+            .do_nothing()
+            .to_owned(),
+    )
+    .do_nothing()
+    .exec(&state.db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    state
+        .revocations
+        .insert(claims.jwt_id, true, remaining(claims));
+
+    Ok(())
+}
+
+/// Revokes `sessions` together with their refresh tokens, ending every token of the
+/// families.
+async fn revoke(state: &AppState, sessions: Vec<Uuid>) -> Result<()> {
+    if sessions.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now().naive_utc();
+    let trx = state.db.begin().await.map_err(Error::SeaOrm)?;
+
+    Session::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(now))
+        .filter(session::Column::Id.is_in(sessions.clone()))
+        .filter(session::Column::RevokedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(now))
+        .filter(refresh_token::Column::SessionId.is_in(sessions.clone()))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    // sessions outlive any access token, a cached entry may expire with the longest one
+    let ttl = Duration::from_secs(state.config.jwt.expiry + state.config.jwt.leeway);
+    for session in sessions {
+        state.revocations.insert(session, true, ttl);
+    }
+
+    Ok(())
+}
+
+/// Revokes a token family: every refresh and access token issued for the session.
+pub async fn revoke_session(state: &AppState, session_id: Uuid) -> Result<()> {
+    revoke(state, vec![session_id]).await
+}
+
+/// Signs the user out everywhere by revoking each of their active sessions.
+pub async fn revoke_user_sessions(state: &AppState, user_id: Uuid) -> Result<()> {
+    let sessions = Session::find()
+        .select_only()
+        .column(session::Column::Id)
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .into_tuple::<Uuid>()
+        .all(&state.db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    revoke(state, sessions).await
+}
+
+/// Revokes every session, refresh token and API key of `user_id` as part of the
+/// transaction `db`, for callers already holding one.
+///
+/// The cache is not told, access tokens of the sessions are rejected once their cached
+/// "not revoked" entry expires.
+pub async fn revoke_user_credentials<C>(db: &C, user_id: Uuid) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    let now = Utc::now().naive_utc();
+
+    Session::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(now))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(now))
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    ApiKey::update_many()
+        .col_expr(api_key::Column::RevokedAt, Expr::value(now))
+        .filter(api_key::Column::UserId.eq(user_id))
+        .filter(api_key::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use lib_core::{error::Error, result::Result};
+use lib_entity::{
+    prelude::{Role, RolePermission, UserRole},
+    role, role_permission,
+    sea_orm_active_enums::{EmployeeRole, PermissionEvent},
+    user_role,
+};
+use sea_orm::{
+    ActiveEnum, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait, TryInsertResult,
+    prelude::Uuid,
+    sea_query::{Expr, OnConflict},
+};
+
+use crate::{
+    JWTClaim, Permission, Scope,
+    audit::{self, Target},
+    scope,
+};
+
+/// Roles assigned to `user_id` together with every role they inherit from.
+pub async fn effective_roles(db: &DatabaseConnection, user_id: Uuid) -> Result<Vec<Uuid>> {
+    let assigned = UserRole::find()
+        .select_only()
+        .column(user_role::Column::RoleId)
+        .filter(user_role::Column::UserId.eq(user_id))
+        .into_tuple::<Uuid>()
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if assigned.is_empty() {
+        return Ok(assigned);
+    }
+
+    // the role table is small, resolving the hierarchy in memory avoids a query per level
+    let parents = Role::find()
+        .select_only()
+        .columns([role::Column::Id, role::Column::ParentId])
+        .into_tuple::<(Uuid, Option<Uuid>)>()
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    Ok(inherit(assigned, &parents))
+}
+
+/// Walks up the parent chain of every role, stopping at roles already visited so a cycle
+/// in the data cannot loop forever.
+fn inherit(assigned: Vec<Uuid>, parents: &HashMap<Uuid, Option<Uuid>>) -> Vec<Uuid> {
+    let mut roles = HashSet::new();
+
+    for mut role in assigned {
+        while roles.insert(role) {
+            match parents.get(&role).copied().flatten() {
+                Some(parent) => role = parent,
+                None => break,
+            }
+        }
+    }
+
+    roles.into_iter().collect()
+}
+
+/// Whether any role of `user_id`, assigned or inherited, requires a second factor.
+pub async fn mfa_required(db: &DatabaseConnection, user_id: Uuid) -> Result<bool> {
+    let roles = effective_roles(db, user_id).await?;
+
+    if roles.is_empty() {
+        return Ok(false);
+    }
+
+    let required = Role::find()
+        .filter(role::Column::Id.is_in(roles))
+        .filter(role::Column::MfaRequired.eq(true))
+        .count(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    Ok(required > 0)
+}
+
+/// Requires a second factor from every holder of the built in role mirroring `role`, or
+/// stops requiring it.
+pub async fn require_mfa(
+    db: &DatabaseConnection,
+    role: EmployeeRole,
+    required: bool,
+) -> Result<()> {
+    let updated = Role::update_many()
+        .col_expr(role::Column::MfaRequired, Expr::value(required))
+        .filter(role::Column::Name.eq(role.to_value()))
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if updated.rows_affected == 0 {
+        return Err(Error::RowNotFound);
+    }
+
+    Ok(())
+}
+
+/// Creates a role that inherits every permission of `parent_id`.
+pub async fn create_role(
+    db: &DatabaseConnection,
+    name: &str,
+    parent_id: Option<Uuid>,
+) -> Result<role::Model> {
+    role::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        name: Set(name.to_string()),
+        parent_id: Set(parent_id),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(Error::SeaOrm)
+}
+
+pub async fn roles(db: &DatabaseConnection) -> Result<Vec<role::Model>> {
+    Role::find()
+        .order_by_asc(role::Column::Name)
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)
+}
+
+/// Roles assigned to `user_id`, without the ones they inherit from.
+pub async fn user_roles(db: &DatabaseConnection, user_id: Uuid) -> Result<Vec<role::Model>> {
+    Role::find()
+        .inner_join(UserRole)
+        .filter(user_role::Column::UserId.eq(user_id))
+        .order_by_asc(role::Column::Name)
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)
+}
+
+/// Grants of `role_id` itself, without the ones it inherits.
+pub async fn role_permissions(
+    db: &DatabaseConnection,
+    role_id: Uuid,
+) -> Result<Vec<role_permission::Model>> {
+    RolePermission::find()
+        .filter(role_permission::Column::RoleId.eq(role_id))
+        .order_by_asc(role_permission::Column::EntityName)
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)
+}
+
+pub async fn assign_role(
+    db: &DatabaseConnection,
+    actor: &JWTClaim,
+    user_id: Uuid,
+    role_id: Uuid,
+) -> Result<()> {
+    let trx = db.begin().await.map_err(Error::SeaOrm)?;
+    assign(&trx, actor, user_id, role_id).await?;
+    trx.commit().await.map_err(Error::SeaOrm)
+}
+
+pub async fn unassign_role(
+    db: &DatabaseConnection,
+    actor: &JWTClaim,
+    user_id: Uuid,
+    role_id: Uuid,
+) -> Result<()> {
+    let trx = db.begin().await.map_err(Error::SeaOrm)?;
+    unassign(&trx, actor, user_id, role_id).await?;
+    trx.commit().await.map_err(Error::SeaOrm)
+}
+
+async fn assign<C>(db: &C, actor: &JWTClaim, user_id: Uuid, role_id: Uuid) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    let inserted = UserRole::insert(user_role::ActiveModel {
+        user_id: Set(user_id),
+        role_id: Set(role_id),
+        granted_by: Set(Some(actor.subject)),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([user_role::Column::UserId, user_role::Column::RoleId])
+            .do_nothing()
+            .to_owned(),
+    )
+    .do_nothing()
+    .exec(db)
+    .await
+    .map_err(Error::SeaOrm)?;
+
+    if let TryInsertResult::Inserted(_) = inserted {
+        audit::record(
+            db,
+            actor,
+            PermissionEvent::Assign,
+            Target::UserRole { user_id, role_id },
+            None,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn unassign<C>(db: &C, actor: &JWTClaim, user_id: Uuid, role_id: Uuid) -> Result<()>
+where
+    C: ConnectionTrait,
+{
+    let deleted = UserRole::delete_many()
+        .filter(user_role::Column::UserId.eq(user_id))
+        .filter(user_role::Column::RoleId.eq(role_id))
+        .exec(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    if deleted.rows_affected > 0 {
+        audit::record(
+            db,
+            actor,
+            PermissionEvent::Unassign,
+            Target::UserRole { user_id, role_id },
+            None,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Grants `permissions` on `table` to every user holding `role_id` or a role inheriting it.
+pub async fn grant_role_permission(
+    db: &DatabaseConnection,
+    actor: &JWTClaim,
+    role_id: Uuid,
+    table: &str,
+    permissions: Vec<Permission>,
+    scope: &Scope,
+) -> Result<()> {
+    scope::validate(table, scope)?;
+
+    let (resource_column, resource_value) = scope.to_columns();
+    let trx = db.begin().await.map_err(Error::SeaOrm)?;
+
+    for perm in permissions.into_iter() {
+        let inserted = RolePermission::insert(role_permission::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            role_id: Set(role_id),
+            entity_name: Set(table.to_string()),
+            action: Set(perm.clone()),
+            resource_column: Set(resource_column.clone()),
+            resource_value: Set(resource_value),
+            granted_by: Set(Some(actor.subject)),
+            granted_at: Set(Utc::now().naive_utc()),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                role_permission::Column::RoleId,
+                role_permission::Column::EntityName,
+                role_permission::Column::Action,
+                role_permission::Column::ResourceColumn,
+                role_permission::Column::ResourceValue,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(&trx)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+        if let TryInsertResult::Inserted(_) = inserted {
+            audit::record(
+                &trx,
+                actor,
+                PermissionEvent::Grant,
+                Target::Role(role_id),
+                Some((table, perm, scope)),
+            )
+            .await?;
+        }
+    }
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(())
+}
+
+pub async fn revoke_role_permission(
+    db: &DatabaseConnection,
+    actor: &JWTClaim,
+    role_id: Uuid,
+    table: &str,
+    permissions: Vec<Permission>,
+    scope: &Scope,
+) -> Result<()> {
+    let trx = db.begin().await.map_err(Error::SeaOrm)?;
+
+    for perm in permissions.into_iter() {
+        let deleted = RolePermission::delete_many()
+            .filter(role_permission::Column::RoleId.eq(role_id))
+            .filter(role_permission::Column::EntityName.eq(table))
+            .filter(role_permission::Column::Action.eq(perm.clone()))
+            .filter(scope.filter(
+                role_permission::Column::ResourceColumn,
+                role_permission::Column::ResourceValue,
+            ))
+            .exec(&trx)
+            .await
+            .map_err(Error::SeaOrm)?;
+
+        if deleted.rows_affected > 0 {
+            audit::record(
+                &trx,
+                actor,
+                PermissionEvent::Revoke,
+                Target::Role(role_id),
+                Some((table, perm, scope)),
+            )
+            .await?;
+        }
+    }
+
+    trx.commit().await.map_err(Error::SeaOrm)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inherits_parent_roles() {
+        let employee = Uuid::new_v4();
+        let manager = Uuid::new_v4();
+        let admin = Uuid::new_v4();
+        let parents = HashMap::from([
+            (employee, None),
+            (manager, Some(employee)),
+            (admin, Some(manager)),
+        ]);
+
+        let mut roles = inherit(vec![admin], &parents);
+        roles.sort();
+        let mut expected = vec![employee, manager, admin];
+        expected.sort();
+        assert_eq!(roles, expected);
+
+        assert_eq!(inherit(vec![employee], &parents), vec![employee]);
+    }
+
+    #[test]
+    fn stops_on_cycles() {
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let parents = HashMap::from([(a, Some(b)), (b, Some(a))]);
+
+        assert_eq!(inherit(vec![a], &parents).len(), 2);
+    }
+}
@@ -0,0 +1,459 @@
+use std::{collections::BTreeSet, str::FromStr};
+
+use lib_core::{error::Error, result::Result};
+use lib_entity::{
+    permissions,
+    prelude::{
+        Department, EmergencyInformation, Employee, File, JobInformation, Permissions,
+        RolePermission, Users,
+    },
+    role_permission,
+};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, IdenStatic, Iterable, ModelTrait,
+    QueryFilter, QuerySelect, Value, prelude::Uuid, sea_query::Expr,
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{JWTClaim, Permission, guard, role};
+
+/// Rows of a table a grant applies to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Scope {
+    /// Every row of the table.
+    #[default]
+    All,
+    /// Rows whose `column` equals `value`, e.g. `id` for a single resource or
+    /// `warehouse_id` for the shipments of a warehouse.
+    Column { column: String, value: Uuid },
+    /// Rows whose `column` equals the id of the grantee, e.g. `owner_id` for own files.
+    Owner { column: String },
+}
+
+impl Scope {
+    /// A single row identified by its `id` primary key.
+    pub fn resource(id: Uuid) -> Self {
+        Self::Column {
+            column: "id".into(),
+            value: id,
+        }
+    }
+
+    /// Reads the scope from the `resource_column` and `resource_value` of a grant.
+    pub fn from_columns(column: Option<String>, value: Option<Uuid>) -> Self {
+        match (column, value) {
+            (Some(column), Some(value)) => Self::Column { column, value },
+            (Some(column), None) => Self::Owner { column },
+            (None, _) => Self::All,
+        }
+    }
+
+    /// `resource_column` and `resource_value` storing the scope in a grant.
+    pub(crate) fn to_columns(&self) -> (Option<String>, Option<Uuid>) {
+        match self {
+            Self::All => (None, None),
+            Self::Column { column, value } => (Some(column.clone()), Some(*value)),
+            Self::Owner { column } => (Some(column.clone()), None),
+        }
+    }
+
+    /// Matches the grants stored with exactly this scope.
+    pub(crate) fn filter<C: ColumnTrait>(
+        &self,
+        resource_column: C,
+        resource_value: C,
+    ) -> Condition {
+        let (column, value) = self.to_columns();
+
+        Condition::all()
+            .add(match column {
+                Some(column) => resource_column.eq(column),
+                None => resource_column.is_null(),
+            })
+            .add(match value {
+                Some(value) => resource_value.eq(value),
+                None => resource_value.is_null(),
+            })
+    }
+}
+
+/// Name and columns of a table.
+type Columns = (String, Vec<String>);
+
+/// Tables whose rows a grant may be narrowed to.
+const SCOPED_TABLES: &[fn() -> Columns] = &[
+    columns::<Department>,
+    columns::<EmergencyInformation>,
+    columns::<Employee>,
+    columns::<File>,
+    columns::<JobInformation>,
+    columns::<Users>,
+];
+
+fn columns<E: EntityTrait>() -> Columns {
+    (
+        E::default().table_name().to_string(),
+        E::Column::iter()
+            .map(|column| column.as_str().to_string())
+            .collect(),
+    )
+}
+
+/// Rejects a `scope` naming a column `table` does not have, such a grant would never match
+/// a row.
+pub fn validate(table: &str, scope: &Scope) -> Result<()> {
+    let (Scope::Column { column, .. } | Scope::Owner { column }) = scope else {
+        return Ok(());
+    };
+
+    let message = match SCOPED_TABLES
+        .iter()
+        .map(|columns| columns())
+        .find(|(name, _)| *name == table)
+    {
+        Some((_, columns)) if columns.contains(column) => return Ok(()),
+        Some(_) => format!("{table} has no column {column}"),
+        None => format!("rows of {table} cannot be scoped"),
+    };
+
+    let mut report = garde::Report::new();
+    report.append(
+        garde::Path::new("scope").join("column"),
+        garde::Error::new(message),
+    );
+
+    Err(Error::Garde(report))
+}
+
+/// Column of `E` named `name`, `None` for grants made before the column was renamed or
+/// dropped, which then grant nothing.
+fn column<E: EntityTrait>(name: &str) -> Option<E::Column> {
+    let column = E::Column::from_str(name).ok();
+
+    if column.is_none() {
+        tracing::warn!(
+            table = E::default().table_name(),
+            column = name,
+            "ignoring a grant scoped to an unknown column"
+        );
+    }
+
+    column
+}
+
+/// Scopes of every grant of any of `permissions` on `table`, direct or through a role.
+///
+/// An empty result means the user may not touch any row of the table.
+pub async fn permitted_scopes(
+    db: &DatabaseConnection,
+    claims: &JWTClaim,
+    table: &str,
+    permissions: Vec<Permission>,
+) -> Result<Vec<Scope>> {
+    let mut grants = Permissions::find()
+        .select_only()
+        .columns([
+            permissions::Column::ResourceColumn,
+            permissions::Column::ResourceValue,
+        ])
+        .filter(permissions::Column::UserId.eq(claims.subject))
+        .filter(permissions::Column::EntityName.eq(table))
+        .filter(permissions::Column::Action.is_in(permissions.clone()))
+        .into_tuple::<(Option<String>, Option<Uuid>)>()
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let roles = role::effective_roles(db, claims.subject).await?;
+
+    if !roles.is_empty() {
+        grants.extend(
+            RolePermission::find()
+                .select_only()
+                .columns([
+                    role_permission::Column::ResourceColumn,
+                    role_permission::Column::ResourceValue,
+                ])
+                .filter(role_permission::Column::RoleId.is_in(roles))
+                .filter(role_permission::Column::EntityName.eq(table))
+                .filter(role_permission::Column::Action.is_in(permissions))
+                .into_tuple::<(Option<String>, Option<Uuid>)>()
+                .all(db)
+                .await
+                .map_err(Error::SeaOrm)?,
+        );
+    }
+
+    let mut scopes = Vec::new();
+    for (column, value) in grants {
+        let scope = Scope::from_columns(column, value);
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    Ok(scopes)
+}
+
+/// `entity:action` scopes of every permission held by `user_id`, direct or through a role,
+/// narrowed to the space delimited `requested` scopes when given.
+///
+/// With `table_wide_only` grants restricted to some rows are left out, a token carrying
+/// their scope would otherwise open the whole table to a guard trusting it alone.
+pub async fn token_scopes(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    requested: Option<&str>,
+    table_wide_only: bool,
+) -> Result<Vec<String>> {
+    let mut grants = Permissions::find()
+        .select_only()
+        .columns([permissions::Column::EntityName, permissions::Column::Action])
+        .filter(permissions::Column::UserId.eq(user_id))
+        .filter(
+            Condition::all()
+                .add_option(table_wide_only.then(|| permissions::Column::ResourceColumn.is_null())),
+        )
+        .into_tuple::<(String, Permission)>()
+        .all(db)
+        .await
+        .map_err(Error::SeaOrm)?;
+
+    let roles = role::effective_roles(db, user_id).await?;
+
+    if !roles.is_empty() {
+        grants.extend(
+            RolePermission::find()
+                .select_only()
+                .columns([
+                    role_permission::Column::EntityName,
+                    role_permission::Column::Action,
+                ])
+                .filter(role_permission::Column::RoleId.is_in(roles))
+                .filter(Condition::all().add_option(
+                    table_wide_only.then(|| role_permission::Column::ResourceColumn.is_null()),
+                ))
+                .into_tuple::<(String, Permission)>()
+                .all(db)
+                .await
+                .map_err(Error::SeaOrm)?,
+        );
+    }
+
+    Ok(narrow(
+        grants
+            .iter()
+            .map(|(table, action)| guard::scope_name(table, action))
+            .collect(),
+        requested,
+    ))
+}
+
+/// Keeps the `held` scopes also found in the space delimited `requested` ones, scopes the
+/// user does not hold are dropped rather than rejected as OAuth2 allows.
+fn narrow(held: BTreeSet<String>, requested: Option<&str>) -> Vec<String> {
+    match requested {
+        Some(requested) => {
+            let requested: BTreeSet<&str> = requested.split_whitespace().collect();
+            held.into_iter()
+                .filter(|scope| requested.contains(scope.as_str()))
+                .collect()
+        }
+        None => held.into_iter().collect(),
+    }
+}
+
+/// Translates `scopes` into a filter on `E` selecting only the rows they grant, so list
+/// queries never load rows the user may not see.
+pub fn condition<E: EntityTrait>(scopes: &[Scope], user_id: Uuid) -> Condition {
+    if scopes.contains(&Scope::All) {
+        return Condition::all();
+    }
+
+    let granted: Vec<_> = scopes
+        .iter()
+        .filter_map(|scope| match scope {
+            Scope::All => None,
+            Scope::Column {
+                column: name,
+                value,
+            } => Some(column::<E>(name)?.eq(*value)),
+            Scope::Owner { column: name } => Some(column::<E>(name)?.eq(user_id)),
+        })
+        .collect();
+
+    if granted.is_empty() {
+        return Condition::all().add(Expr::value(false));
+    }
+
+    granted
+        .into_iter()
+        .fold(Condition::any(), |condition, granted| {
+            condition.add(granted)
+        })
+}
+
+/// Whether `scopes` grant access to the already loaded `model`.
+pub fn permits<M: ModelTrait>(scopes: &[Scope], user_id: Uuid, model: &M) -> bool {
+    scopes.iter().any(|scope| match scope {
+        Scope::All => true,
+        Scope::Column {
+            column: name,
+            value,
+        } => {
+            column::<M::Entity>(name).is_some_and(|column| model.get(column) == Value::from(*value))
+        }
+        Scope::Owner { column: name } => column::<M::Entity>(name)
+            .is_some_and(|column| model.get(column) == Value::from(user_id)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use lib_entity::{file, prelude::File};
+    use sea_orm::{DbBackend, QueryTrait};
+
+    use super::*;
+
+    fn file(owner_id: Uuid) -> file::Model {
+        let now = Utc::now().naive_utc();
+
+        file::Model {
+            id: Uuid::new_v4(),
+            name: "manifest.pdf".into(),
+            file_path: "/manifest.pdf".into(),
+            is_public: false,
+            owner_id,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn sql(condition: Condition) -> String {
+        File::find()
+            .filter(condition)
+            .build(DbBackend::Postgres)
+            .to_string()
+    }
+
+    #[test]
+    fn round_trips_grant_columns() {
+        for scope in [
+            Scope::All,
+            Scope::resource(Uuid::new_v4()),
+            Scope::Owner {
+                column: "owner_id".into(),
+            },
+        ] {
+            let (column, value) = scope.to_columns();
+            assert_eq!(Scope::from_columns(column, value), scope);
+        }
+    }
+
+    #[test]
+    fn deserializes_tagged_scopes() {
+        let scope: Scope =
+            serde_json::from_str(r#"{ "type": "owner", "column": "owner_id" }"#).unwrap();
+        assert_eq!(
+            scope,
+            Scope::Owner {
+                column: "owner_id".into()
+            }
+        );
+
+        let scope: Scope = serde_json::from_str(r#"{ "type": "all" }"#).unwrap();
+        assert_eq!(scope, Scope::All);
+    }
+
+    #[test]
+    fn translates_scopes_into_conditions() {
+        let user = Uuid::new_v4();
+        let shared = Uuid::new_v4();
+
+        let own = sql(condition::<File>(
+            &[
+                Scope::Owner {
+                    column: "owner_id".into(),
+                },
+                Scope::resource(shared),
+            ],
+            user,
+        ));
+        assert!(own.ends_with(&format!(
+            r#"WHERE "file"."owner_id" = '{user}' OR "file"."id" = '{shared}'"#
+        )));
+
+        let all = sql(condition::<File>(
+            &[Scope::resource(shared), Scope::All],
+            user,
+        ));
+        assert!(all.ends_with("WHERE TRUE"));
+
+        let none = sql(condition::<File>(&[], user));
+        assert!(none.ends_with("WHERE FALSE"));
+
+        let unknown = Scope::Owner {
+            column: "warehouse_id".into(),
+        };
+        let unknown = sql(condition::<File>(&[unknown, Scope::resource(shared)], user));
+        assert!(unknown.ends_with(&format!(r#"WHERE "file"."id" = '{shared}'"#)));
+    }
+
+    #[test]
+    fn permits_matching_models() {
+        let user = Uuid::new_v4();
+        let own = Scope::Owner {
+            column: "owner_id".into(),
+        };
+
+        assert!(permits(std::slice::from_ref(&own), user, &file(user)));
+        assert!(!permits(&[own], user, &file(Uuid::new_v4())));
+
+        let other = file(Uuid::new_v4());
+        assert!(permits(&[Scope::resource(other.id)], user, &other));
+        assert!(permits(&[Scope::All], user, &file(Uuid::new_v4())));
+        assert!(!permits(&[], user, &other));
+
+        let unknown = Scope::Owner {
+            column: "warehouse_id".into(),
+        };
+        assert!(!permits(&[unknown], user, &file(user)));
+    }
+
+    #[test]
+    fn validates_scope_columns_against_the_table() {
+        let owner = Scope::Owner {
+            column: "owner_id".into(),
+        };
+        assert!(validate("file", &owner).is_ok());
+        assert!(validate("file", &Scope::resource(Uuid::new_v4())).is_ok());
+        assert!(validate("shipment", &Scope::All).is_ok());
+
+        let unknown = Scope::Owner {
+            column: "warehouse_id".into(),
+        };
+        assert!(matches!(validate("file", &unknown), Err(Error::Garde(_))));
+        assert!(matches!(validate("shipment", &owner), Err(Error::Garde(_))));
+    }
+
+    #[test]
+    fn narrows_held_scopes_to_requested() {
+        let held: BTreeSet<String> = ["shipment:update", "shipment:read", "file:read"]
+            .map(String::from)
+            .into();
+
+        assert_eq!(
+            narrow(held.clone(), None),
+            ["file:read", "shipment:read", "shipment:update"]
+        );
+        assert_eq!(
+            narrow(held.clone(), Some(" shipment:read  file:delete ")),
+            ["shipment:read"]
+        );
+        assert!(narrow(held, Some("")).is_empty());
+    }
+}
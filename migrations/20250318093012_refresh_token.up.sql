@@ -0,0 +1,13 @@
+-- Add up migration script here
+create table
+  logistics.refresh_token (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    token_hash text not null unique,
+    expires_at timestamp not null,
+    revoked_at timestamp,
+    replaced_by uuid references logistics.refresh_token (id) on delete set null,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index refresh_token_user_id_idx on logistics.refresh_token (user_id);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+drop table logistics.password_reset;
+
+drop table logistics.email_verification;
+
+alter table logistics.users
+drop column email_verified_at;
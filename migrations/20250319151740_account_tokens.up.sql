@@ -0,0 +1,28 @@
+-- Add up migration script here
+alter table logistics.users
+add column email_verified_at timestamp;
+
+-- accounts that existed before verification was introduced are trusted
+update logistics.users
+set
+  email_verified_at = create_at;
+
+create table
+  logistics.email_verification (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    token_hash text not null unique,
+    expires_at timestamp not null,
+    used_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
+
+create table
+  logistics.password_reset (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    token_hash text not null unique,
+    expires_at timestamp not null,
+    used_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
@@ -0,0 +1,8 @@
+-- Add down migration script here
+alter table logistics.users
+add constraint users__password_check check (
+  length(_password) > 8
+  and _password ~ '[A-Z]'
+  and _password ~ '[0-9]'
+  and _password ~ '[^a-zA-Z0-9]'
+) not valid;
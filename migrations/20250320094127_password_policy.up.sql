@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- passwords are stored as argon2 hashes, the complexity policy is enforced by the api
+alter table logistics.users
+drop constraint users__password_check;
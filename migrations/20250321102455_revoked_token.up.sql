@@ -0,0 +1,9 @@
+-- Add up migration script here
+create table
+  logistics.revoked_token (
+    jti uuid not null primary key,
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    -- rows can be purged once the revoked token would have expired anyway
+    expires_at timestamp not null,
+    revoked_at timestamp not null default current_timestamp
+  );
@@ -0,0 +1,13 @@
+-- Add up migration script here
+create table
+  logistics.signing_key (
+    kid text not null primary key,
+    algorithm text not null check (algorithm in ('RS256')),
+    -- PKCS#8 PEM encoded private key
+    private_key text not null,
+    created_at timestamp not null default current_timestamp,
+    -- set once a newer key takes over signing
+    retired_at timestamp,
+    -- retired keys keep verifying tokens until this point
+    expires_at timestamp
+  );
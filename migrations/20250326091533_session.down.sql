@@ -0,0 +1,5 @@
+-- Add down migration script here
+alter table logistics.refresh_token
+drop column session_id;
+
+drop table logistics.session;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+-- a session groups every refresh token rotated from the same login (a token family)
+create table
+  logistics.session (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    created_at timestamp not null default current_timestamp,
+    revoked_at timestamp
+  );
+
+create index session_user_id_idx on logistics.session (user_id);
+
+-- refresh tokens issued before families existed cannot be attached to one, their owners
+-- sign in again
+delete from logistics.refresh_token;
+
+alter table logistics.refresh_token
+add column session_id uuid not null references logistics.session (id) on delete cascade;
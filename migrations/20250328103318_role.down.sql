@@ -0,0 +1,6 @@
+-- Add down migration script here
+drop table logistics.user_role;
+
+drop table logistics.role_permission;
+
+drop table logistics.role;
@@ -0,0 +1,81 @@
+-- Add up migration script here
+-- a role bundles permissions, a role inherits every permission of its parent
+create table
+  logistics.role (
+    id uuid not null primary key default gen_random_uuid (),
+    name text not null unique,
+    parent_id uuid references logistics.role (id) on delete set null check (parent_id <> id),
+    created_at timestamp not null default current_timestamp
+  );
+
+create table
+  logistics.role_permission (
+    id uuid not null primary key default gen_random_uuid (),
+    role_id uuid not null references logistics.role (id) on delete cascade,
+    entity_name text not null,
+    action text not null check (action in ('create', 'read', 'update', 'delete')),
+    unique (role_id, entity_name, action)
+  );
+
+create table
+  logistics.user_role (
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    role_id uuid not null references logistics.role (id) on delete cascade,
+    created_at timestamp not null default current_timestamp,
+    primary key (user_id, role_id)
+  );
+
+create index user_role_role_id_idx on logistics.user_role (role_id);
+
+-- built in roles mirroring logistics.employee_role, each one extends the previous
+insert into
+  logistics.role (name)
+values
+  ('Employee');
+
+insert into
+  logistics.role (name, parent_id)
+select
+  'Manager',
+  id
+from
+  logistics.role
+where
+  name = 'Employee';
+
+insert into
+  logistics.role (name, parent_id)
+select
+  'Admin',
+  id
+from
+  logistics.role
+where
+  name = 'Manager';
+
+-- employees keep the permissions of their employee role, on schemas where the employee
+-- table records it and the account it is bound to
+do $$
+begin
+  if (
+    select
+      count(*)
+    from
+      information_schema.columns
+    where
+      table_schema = 'logistics'
+      and table_name = 'employee'
+      and column_name in ('role', 'auth_user_id')
+  ) = 2 then
+    insert into
+      logistics.user_role (user_id, role_id)
+    select
+      employee.auth_user_id,
+      role.id
+    from
+      logistics.employee
+      join logistics.role on role.name = employee.role::text
+    on conflict do nothing;
+  end if;
+end
+$$;
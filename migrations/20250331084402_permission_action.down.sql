@@ -0,0 +1,13 @@
+-- Add down migration script here
+alter table logistics.permissions
+drop constraint permissions_user_id_entity_name_action_key;
+
+alter table logistics.role_permission
+alter column action type text,
+add constraint role_permission_action_check check (action in ('create', 'read', 'update', 'delete'));
+
+alter table logistics.permissions
+alter column action type text,
+add constraint permissions_action_check check (action in ('create', 'read', 'update', 'delete'));
+
+drop type logistics.permission_action;
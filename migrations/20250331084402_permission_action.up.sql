@@ -0,0 +1,21 @@
+-- Add up migration script here
+create type logistics.permission_action as enum('create', 'read', 'update', 'delete');
+
+alter table logistics.permissions
+drop constraint permissions_action_check,
+alter column action type logistics.permission_action using action::logistics.permission_action;
+
+alter table logistics.role_permission
+drop constraint role_permission_action_check,
+alter column action type logistics.permission_action using action::logistics.permission_action;
+
+-- a direct grant is unique like a role grant, duplicates are folded before enforcing it
+delete from logistics.permissions a using logistics.permissions b
+where
+  a.id > b.id
+  and a.user_id = b.user_id
+  and a.entity_name = b.entity_name
+  and a.action = b.action;
+
+alter table logistics.permissions
+add constraint permissions_user_id_entity_name_action_key unique (user_id, entity_name, action);
@@ -0,0 +1,22 @@
+-- Add down migration script here
+delete from logistics.role_permission
+where
+  resource_column is not null;
+
+alter table logistics.role_permission
+drop constraint role_permission_grant_key,
+drop constraint role_permission_resource_check,
+drop column resource_value,
+drop column resource_column,
+add constraint role_permission_role_id_entity_name_action_key unique (role_id, entity_name, action);
+
+delete from logistics.permissions
+where
+  resource_column is not null;
+
+alter table logistics.permissions
+drop constraint permissions_grant_key,
+drop constraint permissions_resource_check,
+drop column resource_value,
+drop column resource_column,
+add constraint permissions_user_id_entity_name_action_key unique (user_id, entity_name, action);
@@ -0,0 +1,34 @@
+-- Add up migration script here
+-- a grant without a resource column covers the whole table, otherwise only rows whose
+-- resource column equals resource_value, or the grantee id when resource_value is null
+alter table logistics.permissions
+drop constraint permissions_user_id_entity_name_action_key,
+add column resource_column text,
+add column resource_value uuid,
+add constraint permissions_resource_check check (
+  resource_column is not null
+  or resource_value is null
+),
+add constraint permissions_grant_key unique nulls not distinct (
+  user_id,
+  entity_name,
+  action,
+  resource_column,
+  resource_value
+);
+
+alter table logistics.role_permission
+drop constraint role_permission_role_id_entity_name_action_key,
+add column resource_column text,
+add column resource_value uuid,
+add constraint role_permission_resource_check check (
+  resource_column is not null
+  or resource_value is null
+),
+add constraint role_permission_grant_key unique nulls not distinct (
+  role_id,
+  entity_name,
+  action,
+  resource_column,
+  resource_value
+);
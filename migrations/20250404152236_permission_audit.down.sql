@@ -0,0 +1,15 @@
+-- Add down migration script here
+drop table logistics.permission_audit;
+
+drop type logistics.permission_event;
+
+alter table logistics.user_role
+drop column granted_by;
+
+alter table logistics.role_permission
+drop column granted_at,
+drop column granted_by;
+
+alter table logistics.permissions
+drop column granted_at,
+drop column granted_by;
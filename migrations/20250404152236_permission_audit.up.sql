@@ -0,0 +1,33 @@
+-- Add up migration script here
+alter table logistics.permissions
+add column granted_by uuid references logistics.users (id) on delete set null,
+add column granted_at timestamp not null default current_timestamp;
+
+alter table logistics.role_permission
+add column granted_by uuid references logistics.users (id) on delete set null,
+add column granted_at timestamp not null default current_timestamp;
+
+alter table logistics.user_role
+add column granted_by uuid references logistics.users (id) on delete set null;
+
+create type logistics.permission_event as enum('grant', 'revoke', 'assign', 'unassign');
+
+-- append only, rows outlive the users and roles they mention: the ids carry no foreign
+-- key so deleting either leaves the trail intact
+create table
+  logistics.permission_audit (
+    id uuid not null primary key default gen_random_uuid (),
+    event logistics.permission_event not null,
+    actor_id uuid,
+    user_id uuid,
+    role_id uuid,
+    entity_name text,
+    action logistics.permission_action,
+    resource_column text,
+    resource_value uuid,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index permission_audit_user_id_idx on logistics.permission_audit (user_id);
+
+create index permission_audit_role_id_idx on logistics.permission_audit (role_id);
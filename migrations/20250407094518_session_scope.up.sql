@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- space delimited scope requested at login, every refreshed access token stays within it
+alter table logistics.session
+add column scope text;
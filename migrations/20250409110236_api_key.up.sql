@@ -0,0 +1,19 @@
+-- Add up migration script here
+-- long lived credentials of integrations that cannot sign in interactively
+create table
+  logistics.api_key (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    name text not null,
+    -- first characters of the secret, lets owners tell their keys apart
+    prefix text not null,
+    token_hash text not null unique,
+    -- space delimited scopes, the key never grants more than its owner holds
+    scope text not null,
+    expires_at timestamp,
+    last_used_at timestamp,
+    revoked_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index api_key_user_id_idx on logistics.api_key (user_id);
@@ -0,0 +1,9 @@
+-- Add down migration script here
+drop table logistics.mfa_challenge;
+
+drop table logistics.recovery_code;
+
+drop table logistics.user_totp;
+
+alter table logistics.role
+drop column mfa_required;
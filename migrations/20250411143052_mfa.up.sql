@@ -0,0 +1,44 @@
+-- Add up migration script here
+-- accounts holding a role that requires it must enroll a second factor before using
+-- their permissions, required roles also bind every role extending them
+alter table logistics.role
+add column mfa_required boolean not null default false;
+
+-- the secret is needed in clear to compute codes, it is only enabled once a first code
+-- proved the authenticator app holds it
+create table
+  logistics.user_totp (
+    user_id uuid not null primary key references logistics.users (id) on delete cascade,
+    secret text not null,
+    -- last accepted period, codes are single use
+    last_step bigint,
+    enabled_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
+
+create table
+  logistics.recovery_code (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    code_hash text not null unique,
+    used_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index recovery_code_user_id_idx on logistics.recovery_code (user_id);
+
+-- issued by a password login of an account with a second factor, exchanged for tokens
+-- together with a code
+create table
+  logistics.mfa_challenge (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    token_hash text not null unique,
+    scope text,
+    attempts integer not null default 0,
+    expires_at timestamp not null,
+    used_at timestamp,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index mfa_challenge_user_id_idx on logistics.mfa_challenge (user_id);
@@ -0,0 +1,20 @@
+-- Add down migration script here
+drop table logistics.oidc_login;
+
+drop table logistics.user_identity;
+
+-- enum values cannot be dropped, the type is recreated without it. Accounts that only
+-- exist through a provider cannot sign in anymore and are removed.
+delete from logistics.users
+where
+  auth_type = 'oidc';
+
+alter type logistics.auth_type
+rename to auth_type_old;
+
+create type logistics.auth_type as enum('basic_auth');
+
+alter table logistics.users
+alter column auth_type type logistics.auth_type using auth_type::text::logistics.auth_type;
+
+drop type logistics.auth_type_old;
@@ -0,0 +1,32 @@
+-- Add up migration script here
+-- accounts created by signing in with an external OpenID Connect provider, they have no
+-- usable password
+alter type logistics.auth_type
+add value 'oidc';
+
+-- a user of an external provider, known by the issuer and its subject identifier
+create table
+  logistics.user_identity (
+    id uuid not null primary key default gen_random_uuid (),
+    user_id uuid not null references logistics.users (id) on delete cascade,
+    issuer text not null,
+    subject text not null,
+    email text,
+    created_at timestamp not null default current_timestamp,
+    last_login_at timestamp,
+    unique (issuer, subject)
+  );
+
+create index user_identity_user_id_idx on logistics.user_identity (user_id);
+
+-- authorization requests sent to the provider, consumed by the callback
+create table
+  logistics.oidc_login (
+    id uuid not null primary key default gen_random_uuid (),
+    state_hash text not null unique,
+    nonce text not null,
+    code_verifier text not null,
+    scope text,
+    expires_at timestamp not null,
+    created_at timestamp not null default current_timestamp
+  );
@@ -0,0 +1,6 @@
+-- Add down migration script here
+drop table logistics.login_throttle;
+
+drop table logistics.auth_event;
+
+drop type logistics.auth_event_type;
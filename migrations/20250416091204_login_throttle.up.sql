@@ -0,0 +1,32 @@
+-- Add up migration script here
+create type logistics.auth_event_type as enum('login_success', 'login_failure', 'throttled', 'lockout', 'unlock');
+
+-- append only, rows outlive the users they mention
+create table
+  logistics.auth_event (
+    id uuid not null primary key default gen_random_uuid (),
+    event logistics.auth_event_type not null,
+    user_id uuid references logistics.users (id) on delete set null,
+    -- as submitted, also set for unknown accounts
+    email text,
+    ip text,
+    user_agent text,
+    -- administrator lifting a lockout
+    actor_id uuid references logistics.users (id) on delete set null,
+    created_at timestamp not null default current_timestamp
+  );
+
+create index auth_event_user_id_idx on logistics.auth_event (user_id);
+
+create index auth_event_created_at_idx on logistics.auth_event (created_at);
+
+-- failed login counters, keyed by the normalized email or the client address
+create table
+  logistics.login_throttle (
+    kind text not null check (kind in ('account', 'ip')),
+    subject text not null,
+    failures integer not null default 0,
+    last_failure_at timestamp not null default current_timestamp,
+    locked_until timestamp,
+    primary key (kind, subject)
+  );
@@ -1,37 +1,129 @@
-use axum::Router;
-use hmac::{Hmac, Mac};
-use lib_core::AppState;
-use sea_orm::Database;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use lib_core::{
+    AppState,
+    config::Config,
+    keys::KeyRing,
+    mailer::{FileMailer, LogMailer},
+    middleware, shutdown, telemetry,
+};
+use lib_security::keys::Rotation;
+use sea_orm::{ConnectOptions, Database};
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_scalar::{Scalar, Servable};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let db = Database::connect(std::env::var("RUST_DATABASE_URL")?).await?;
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    let telemetry = telemetry::init(&config.log)?;
+
+    for var in &config.unknown_vars {
+        tracing::warn!("environment variable {var} names no setting and is ignored");
+    }
+
+    let mut options = ConnectOptions::new(&config.database.url);
+    // enum casts generated by sea-orm name the type without its schema
+    options.set_schema_search_path("logistics,public");
+    let db = Database::connect(options).await?;
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn(shutdown::cancel_on_signal(shutdown.clone()));
 
-    let (port, address) = (std::env::var("RUST_PORT")?, std::env::var("RUST_ADDRESS")?);
+    let keys = Arc::new(KeyRing::default());
+    let rotation = Rotation {
+        interval: Duration::from_secs(config.jwt.key_rotation),
+        retention: Duration::from_secs(config.jwt.expiry + config.jwt.leeway),
+    };
 
-    let listener = TcpListener::bind(format!("{}:{}", address, port)).await?;
+    let passphrase = config.jwt.key_passphrase.clone();
+    if passphrase.is_none() {
+        tracing::warn!("jwt.key_passphrase is unset, signing keys are stored unencrypted");
+    }
 
-    let (router, openapi) = OpenApiRouter::with_openapi(lib_core::docs::OpenAPI::openapi())
-        .nest("/api/v1/auth", api_auth::routes())
+    lib_security::keys::rotate_if_due(&db, &rotation, passphrase.as_deref()).await?;
+    lib_security::keys::load(&db, &keys, passphrase.as_deref()).await?;
+    let key_schedule = tokio::spawn(lib_security::keys::schedule(
+        db.clone(),
+        keys.clone(),
+        rotation,
+        passphrase,
+        shutdown.clone(),
+    ));
+
+    let listener = TcpListener::bind((config.server.address.as_str(), config.server.port)).await?;
+
+    let state = AppState {
+        config: config.clone(),
+        db: db.clone(),
+        keys,
+        revocations: Arc::default(),
+        password_params: config.argon2.params()?,
+        mailer: match &config.mail.directory {
+            Some(directory) => Arc::new(FileMailer::new(directory)),
+            None => Arc::new(LogMailer),
+        },
+        oidc_discovery: Arc::default(),
+        shutdown: shutdown.clone(),
+    };
+
+    let api = OpenApiRouter::new()
+        .nest("/api/v1/auth", api_auth::routes(&state))
         .nest("/api/v1/human-resource", api_human_resource::routes())
-        .nest("/api/v1/inventory", api_inventory::routes())
-        .nest("/api/v1/file", api_file::routes())
+        .nest("/api/v1/inventory", api_inventory::routes(&state))
         .nest("/api/v1/chat", api_chat::routes())
         .nest("/api/v1/sales", api_sales::routes())
+        .merge(api_auth::well_known())
+        .merge(api_monitoring::routes(env!("CARGO_PKG_VERSION")))
+        .layer(middleware::body_limit(config.server.body_limit));
+
+    let uploads = OpenApiRouter::new()
+        .nest("/api/v1/file", api_file::routes())
+        .layer(middleware::body_limit(config.file.max_upload_size));
+
+    let (router, mut openapi) = OpenApiRouter::with_openapi(lib_core::docs::OpenAPI::openapi())
+        .merge(api)
+        .merge(uploads)
         .split_for_parts();
 
+    lib_core::docs::OpenAPI::document_problems(&mut openapi);
+
     let router = router
         .merge(Scalar::with_url("/scalar", openapi))
-        .with_state(AppState {
-            db,
-            key: Hmac::new_from_slice(std::env::var("RUST_JWT_ACCESS_KEY")?.as_bytes())?,
-        });
+        .with_state(state)
+        .layer(middleware::stack(&config));
+
+    tracing::info!(address = %listener.local_addr()?, "listening");
+
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+
+    shutdown::drain(
+        server,
+        &shutdown,
+        Duration::from_secs(config.server.shutdown_timeout),
+    )
+    .await?;
+
+    // a server stopping on its own still takes the background tasks down with it
+    shutdown.cancel();
+    key_schedule.await?;
+    db.close().await?;
 
-    axum::serve(listener, router.into_make_service()).await?;
+    tracing::info!("shut down");
+    telemetry.shutdown();
 
     Ok(())
 }